@@ -0,0 +1,113 @@
+use clap::{ArgAction, Parser, Subcommand};
+use std::path::PathBuf;
+
+#[derive(Parser)]
+#[command(
+    name = "ytx-initdb",
+    version,
+    about = "Database initialization tool for YTX"
+)]
+pub struct Cli {
+    /// Path to the env file to load before reading configuration
+    #[arg(long, global = true, value_name = "PATH")]
+    pub env_file: Option<PathBuf>,
+
+    /// Path to the TOML config file, defaults to ytx-initdb.toml if it exists
+    #[arg(long, global = true, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// Increase output verbosity (-v prints executed SQL, -vv prints everything)
+    #[arg(short, long, global = true, action = ArgAction::Count, conflicts_with = "quiet")]
+    pub verbose: u8,
+
+    /// Only print warnings and errors
+    #[arg(short, long, global = true)]
+    pub quiet: bool,
+
+    /// Print the SQL that would run instead of executing it, existence checks still run
+    #[arg(long, global = true)]
+    pub dry_run: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Create databases and roles, apply migrations and grant permissions (default)
+    Init {
+        /// Generate passwords for roles that have none in Vault and store them there
+        #[arg(long)]
+        bootstrap_vault: bool,
+        /// Continue a failed run, skipping the steps its journal records as done
+        #[arg(long)]
+        resume: bool,
+    },
+    /// Manage workspaces and their main databases
+    Workspace {
+        #[command(subcommand)]
+        command: WorkspaceCommand,
+    },
+    /// Hand the YTX roles over to Vault
+    Vault {
+        #[command(subcommand)]
+        command: VaultCommand,
+    },
+    /// Generate a new password for a YTX role, store it in Vault and apply it
+    RotatePassword {
+        /// One of the YTX roles
+        role: String,
+    },
+    /// Reset passwords and attributes of existing YTX roles to the expected state
+    Reconcile,
+    /// Apply pending schema migrations and indexes to the auth and all main databases
+    Migrate {
+        /// Build missing indexes with CREATE INDEX CONCURRENTLY, for live databases
+        #[arg(long)]
+        concurrently: bool,
+    },
+    /// Re-apply role permissions on the auth and all main databases
+    Grant,
+    /// Show databases, roles, schema versions and workspaces
+    Status,
+    /// Check that the installation is complete, exit with an error otherwise
+    Verify,
+    /// Print the effective configuration with secrets redacted
+    Config,
+    /// Drop a workspace's main database, or with --all everything the tool created
+    Teardown {
+        /// Workspace whose main database and registration are dropped
+        #[arg(long, conflicts_with = "all", required_unless_present = "all")]
+        workspace: Option<String>,
+        /// Drop all main databases, the auth database and the YTX roles
+        #[arg(long)]
+        all: bool,
+        /// Name of the target to confirm: the workspace, or the auth database with --all
+        #[arg(long, value_name = "TARGET")]
+        confirm: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum VaultCommand {
+    /// Configure Vault's database secrets engine with static roles for the YTX roles
+    Database,
+}
+
+#[derive(Subcommand)]
+pub enum WorkspaceCommand {
+    /// Create, initialize and grant a main database and link it to a workspace
+    Add {
+        /// Workspace name
+        name: String,
+        /// Main database for the workspace, defaults to the workspace name
+        #[arg(long)]
+        database: Option<String>,
+    },
+    /// Add every workspace of a manifest, continuing past failures, and print a summary
+    Sync {
+        /// TOML file of [[workspaces]] entries, defaults to those in the config file
+        #[arg(long, value_name = "PATH")]
+        manifest: Option<PathBuf>,
+    },
+}
@@ -0,0 +1,907 @@
+use crate::config::*;
+use crate::connection::*;
+use crate::database::*;
+use crate::drift::*;
+use crate::foreign_key::*;
+use crate::index::*;
+use crate::journal::*;
+use crate::migration::*;
+use crate::settings::*;
+use crate::vault::*;
+
+use anyhow::{Context, Result, bail};
+use log::{error, info};
+use postgres::config::Host;
+use serde_json::{Value, json};
+use std::iter;
+use std::path::Path;
+
+// Every step is recorded in the journal, so that a failed run can be resumed
+// with `--resume` from the step that failed.
+pub fn init(
+    config: &Config,
+    connector: &Connector,
+    bootstrap_vault: bool,
+    resume: bool,
+) -> Result<()> {
+    let mut postgres_session = connector.server()?;
+
+    let passwords = if bootstrap_vault {
+        let mut existing_roles = Vec::new();
+        for role in config.ytx_roles() {
+            if role_exists(&mut postgres_session, role)? {
+                existing_roles.push(role);
+            }
+        }
+        config.bootstrap_role_passwords(&existing_roles, connector.dry_run())?
+    } else {
+        config.role_passwords()?
+    };
+
+    // Checked before the first step, so that a bad entry does not leave a
+    // half-provisioned installation behind.
+    for workspace in &config.workspaces {
+        validate_file_workspace(config, workspace)?;
+    }
+
+    let mut journal = Journal::open(connector, &config.lock_database, resume)?;
+
+    journal.step(&format!("create database {}", config.auth_db), || {
+        create_database(&mut postgres_session, &config.auth_db)
+    })?;
+
+    for (role, password) in [
+        (&config.auth_readwrite_role, &passwords.auth_readwrite),
+        (&config.main_readonly_role, &passwords.main_readonly),
+        (&config.main_readwrite_role, &passwords.main_readwrite),
+    ] {
+        journal.step(&format!("create role {}", role), || {
+            create_role(&mut postgres_session, role, password)
+        })?;
+    }
+
+    let mut auth_session = connector.session(&config.auth_db)?;
+    journal.step(&format!("migrate {}", config.auth_db), || {
+        initialize_auth_database(&mut auth_session)
+    })?;
+
+    // The main workspace first, then those listed in the config file.
+    let workspaces = iter::once((&config.main_workspace, &config.main_db)).chain(
+        config
+            .workspaces
+            .iter()
+            .map(|workspace| (&workspace.name, &workspace.database)),
+    );
+
+    for (workspace, main_db) in workspaces {
+        journal.step(&format!("create database {}", main_db), || {
+            create_database(&mut postgres_session, main_db)
+        })?;
+
+        let mut main_session = connector.session(main_db)?;
+        journal.step(&format!("migrate {}", main_db), || {
+            initialize_main_database(&mut main_session, config.schema_profile, false)
+        })?;
+        journal.step(&format!("grant {}", main_db), || {
+            grant_main_permission(config, &mut postgres_session, &mut main_session, main_db)
+        })?;
+
+        journal.step(&format!("link workspace {}", workspace), || {
+            insert_workspace_database(&mut auth_session, workspace, main_db)
+        })?;
+    }
+
+    journal.step(&format!("grant {}", config.auth_db), || {
+        grant_readwrite_permission(
+            &mut postgres_session,
+            &mut auth_session,
+            &config.auth_db,
+            &config.auth_readwrite_role,
+        )
+    })
+}
+
+pub fn add_workspace(
+    config: &Config,
+    connector: &Connector,
+    workspace: &str,
+    database: Option<&str>,
+) -> Result<()> {
+    validate_workspace("workspace", workspace)?;
+    let database = match database {
+        Some(database) => {
+            validate_value("--database", database)?;
+            database
+        }
+        None => {
+            validate_value("database", workspace).context(
+                "Workspace name is not a valid database name, pass one explicitly with --database",
+            )?;
+            workspace
+        }
+    };
+
+    let mut postgres_session = connector.server()?;
+    check_initialized(config, &mut postgres_session)?;
+
+    let mut auth_session = connector.session(&config.auth_db)?;
+    provision_workspace(
+        config,
+        connector,
+        &mut postgres_session,
+        &mut auth_session,
+        workspace,
+        database,
+    )?;
+
+    info!("Workspace '{}' is ready.", workspace);
+    Ok(())
+}
+
+// Each workspace is provisioned on its own, a failed one is reported in the
+// summary and does not stop the rest.
+pub fn sync_workspaces(
+    config: &Config,
+    connector: &Connector,
+    manifest: Option<&Path>,
+) -> Result<()> {
+    let manifest_workspaces;
+    let workspaces = match manifest {
+        Some(path) => {
+            manifest_workspaces = load_manifest(path)?;
+            &manifest_workspaces
+        }
+        None => &config.workspaces,
+    };
+
+    if workspaces.is_empty() {
+        bail!("No workspaces to add, pass --manifest or list [[workspaces]] in the config file");
+    }
+
+    let mut postgres_session = connector.server()?;
+    check_initialized(config, &mut postgres_session)?;
+
+    let mut auth_session = connector.session(&config.auth_db)?;
+    let mut results = Vec::new();
+
+    for workspace in workspaces {
+        let result = validate_file_workspace(config, workspace).and_then(|_| {
+            provision_workspace(
+                config,
+                connector,
+                &mut postgres_session,
+                &mut auth_session,
+                &workspace.name,
+                &workspace.database,
+            )
+        });
+
+        match &result {
+            Ok(()) => info!("Workspace '{}' is ready.", workspace.name),
+            Err(e) => error!("Workspace '{}' failed: {:#}", workspace.name, e),
+        }
+        results.push((workspace, result));
+    }
+
+    // A dry run prints the plan to stdout, the summary stays an SQL comment.
+    let prefix = if connector.dry_run() { "-- " } else { "" };
+    println!("{}Workspaces:", prefix);
+    for (workspace, result) in &results {
+        let state = if result.is_ok() { "ok" } else { "failed" };
+        println!(
+            "{}  {:<24} -> {:<24} {}",
+            prefix, workspace.name, workspace.database, state
+        );
+        if let Err(e) = result {
+            println!("{}    {:#}", prefix, e);
+        }
+    }
+
+    let failed = results.iter().filter(|(_, result)| result.is_err()).count();
+    if failed > 0 {
+        bail!("{} of {} workspace(s) failed", failed, results.len());
+    }
+
+    Ok(())
+}
+
+fn validate_file_workspace(config: &Config, workspace: &FileWorkspace) -> Result<()> {
+    validate_workspace("workspaces.name", &workspace.name)?;
+    validate_value("workspaces.database", &workspace.database)?;
+    if workspace.database == config.auth_db {
+        bail!(
+            "Workspace '{}' cannot use the auth database `{}`",
+            workspace.name,
+            workspace.database
+        );
+    }
+
+    Ok(())
+}
+
+// Workspaces are added to an installation, `init` creates the auth database
+// and the roles they depend on.
+fn check_initialized(config: &Config, postgres_session: &mut Session) -> Result<()> {
+    if !database_exists(postgres_session, &config.auth_db)? {
+        bail!(
+            "Auth database `{}` does not exist, run `init` first",
+            config.auth_db
+        );
+    }
+
+    for role in [&config.main_readonly_role, &config.main_readwrite_role] {
+        if !role_exists(postgres_session, role)? {
+            bail!("Role {} does not exist, run `init` first", role);
+        }
+    }
+
+    Ok(())
+}
+
+fn provision_workspace(
+    config: &Config,
+    connector: &Connector,
+    postgres_session: &mut Session,
+    auth_session: &mut Session,
+    workspace: &str,
+    database: &str,
+) -> Result<()> {
+    if database == config.auth_db {
+        bail!("Workspace cannot use the auth database `{}`", database);
+    }
+
+    // Refuse before creating anything if the workspace points elsewhere.
+    if let Some(existing_db) = workspace_database(auth_session, workspace)?
+        && existing_db != database
+    {
+        bail!(
+            "Workspace '{}' is already linked to database '{}'",
+            workspace,
+            existing_db
+        );
+    }
+
+    provision_main_database(config, postgres_session, connector, database)?;
+    insert_workspace_database(auth_session, workspace, database)
+}
+
+pub fn reconcile(config: &Config, connector: &Connector) -> Result<()> {
+    let passwords = config.role_passwords()?;
+    let mut postgres_session = connector.server()?;
+
+    for (role, password) in [
+        (&config.auth_readwrite_role, &passwords.auth_readwrite),
+        (&config.main_readonly_role, &passwords.main_readonly),
+        (&config.main_readwrite_role, &passwords.main_readwrite),
+    ] {
+        reconcile_role(&mut postgres_session, role, password)?;
+    }
+
+    Ok(())
+}
+
+// Stores a new password in Vault with check-and-set, applies it with ALTER
+// ROLE and confirms that a login with it works. If a step after the Vault
+// write fails, the role and Vault are rolled back to the previous password.
+pub fn rotate_password(config: &Config, connector: &Connector, role: &str) -> Result<()> {
+    let Some(vault) = &config.vault else {
+        bail!("Password rotation requires Vault credentials");
+    };
+
+    if !config.ytx_roles().contains(&role) {
+        bail!(
+            "Role {} is not a YTX role, expected one of {}",
+            role,
+            config.ytx_roles().join(", ")
+        );
+    }
+
+    if config.ytx_secret.version.is_some() {
+        bail!("YTX_SECRET_VERSION pins the role passwords, unset it to rotate them");
+    }
+
+    let secret = vault
+        .read_secret(&config.ytx_secret.path)
+        .context("Failed to read YTX role passwords from Vault")?
+        .with_context(|| format!("No role passwords stored at {}", config.ytx_secret.path))?;
+    let previous = get_vault_password(&Value::Object(secret.data.clone()), role)?;
+
+    let password = generate_password()?;
+    let mut data = secret.data.clone();
+    data.insert(role.to_string(), Value::String(password.clone()));
+
+    let mut postgres_session = connector.server()?;
+    if !role_exists(&mut postgres_session, role)? {
+        bail!("Role {} does not exist, run `init` first", role);
+    }
+
+    // The auth role can only connect to the auth database.
+    let database = if role == config.auth_readwrite_role {
+        &config.auth_db
+    } else {
+        &config.main_db
+    };
+
+    if connector.dry_run() {
+        println!(
+            "-- Vault: store new password for {} at {} (cas={})",
+            role, config.ytx_secret.path, secret.version
+        );
+        set_role_password(&mut postgres_session, role, &password)?;
+        println!("-- login check as {} on {}", role, database);
+        return Ok(());
+    }
+
+    vault
+        .write_secret(&config.ytx_secret.path, &data, secret.version)
+        .with_context(|| {
+            format!(
+                "Failed to store the new password at {}, it may have been changed concurrently",
+                config.ytx_secret.path
+            )
+        })?;
+    info!("New password for {} stored in Vault.", role);
+
+    let applied = set_role_password(&mut postgres_session, role, &password)
+        .and_then(|_| connector.check_login(role, &password, database));
+
+    let Err(e) = applied else {
+        info!("Password of role {} rotated.", role);
+        return Ok(());
+    };
+
+    error!("Rotation of {} failed, rolling back: {:#}", role, e);
+
+    // Restore the old password in PostgreSQL first, it may already have been
+    // changed. Vault follows only once PostgreSQL is known to hold the old
+    // password again, otherwise Vault keeps the new one for `reconcile` to apply.
+    if let Err(restore) = set_role_password(&mut postgres_session, role, &previous) {
+        error!(
+            "Failed to restore the previous password of {}: {:#}",
+            role, restore
+        );
+        return Err(e.context(format!(
+            "Password rotation of {} failed and the previous password could not be restored. \
+             The new password in Vault (version {} of {}) is the live credential, \
+             run `reconcile` to make sure PostgreSQL uses it",
+            role,
+            secret.version + 1,
+            config.ytx_secret.path
+        )));
+    }
+
+    vault
+        .write_secret(&config.ytx_secret.path, &secret.data, secret.version + 1)
+        .with_context(|| {
+            format!(
+                "Failed to roll back {} to version {}, restore it manually",
+                config.ytx_secret.path, secret.version
+            )
+        })?;
+    info!(
+        "Vault secret {} rolled back to the data of version {}.",
+        config.ytx_secret.path, secret.version
+    );
+
+    Err(e.context(format!("Password rotation of {} failed", role)))
+}
+
+// Registers the PostgreSQL server with Vault's database secrets engine and
+// turns the YTX roles into static roles that Vault rotates from then on.
+pub fn configure_vault_database(config: &Config, connector: &Connector) -> Result<()> {
+    let Some(vault) = &config.vault else {
+        bail!("Configuring the database secrets engine requires Vault credentials");
+    };
+    let engine = &config.vault_database;
+
+    let mut postgres_session = connector.server()?;
+
+    // Static roles take over existing roles. Missing ones get a throwaway
+    // password, Vault rotates it as soon as the static role is created.
+    for role in config.ytx_roles() {
+        if !role_exists(&mut postgres_session, role)? {
+            create_role(&mut postgres_session, role, &generate_password()?)?;
+        }
+    }
+
+    let connection_url = match &engine.connection_url {
+        Some(url) => url.clone(),
+        None => vault_connection_url(&config.postgres)?,
+    };
+
+    let connection_path = format!("{}/config/{}", engine.mount, engine.connection);
+    let connection = json!({
+        "plugin_name": "postgresql-database-plugin",
+        "connection_url": connection_url,
+        "username": config.postgres_role,
+        "password": connector
+            .password()
+            .context("Vault needs the password of the superuser, none is configured")?,
+        "allowed_roles": config.ytx_roles(),
+        "verify_connection": true,
+    });
+
+    if connector.dry_run() {
+        println!(
+            "-- Vault: write {} (connection_url={}, username={}, allowed_roles={})",
+            connection_path,
+            connection_url,
+            config.postgres_role,
+            config.ytx_roles().join(",")
+        );
+    } else {
+        vault
+            .write(&connection_path, &connection)
+            .with_context(|| {
+                format!(
+                    "Failed to configure Vault database connection {}",
+                    connection_path
+                )
+            })?;
+        info!("Vault database connection {} configured.", connection_path);
+    }
+
+    for role in config.ytx_roles() {
+        let role_path = format!("{}/static-roles/{}", engine.mount, role);
+        let static_role = json!({
+            "db_name": engine.connection,
+            "username": role,
+            "rotation_period": engine.rotation_period,
+        });
+
+        if connector.dry_run() {
+            println!(
+                "-- Vault: write {} (rotation_period={})",
+                role_path, engine.rotation_period
+            );
+            continue;
+        }
+
+        vault
+            .write(&role_path, &static_role)
+            .with_context(|| format!("Failed to create Vault static role {}", role_path))?;
+        info!(
+            "Vault static role {} configured, rotated every {}.",
+            role, engine.rotation_period
+        );
+    }
+
+    Ok(())
+}
+
+pub fn migrate(config: &Config, connector: &Connector, concurrently: bool) -> Result<()> {
+    let mut auth_session = connector.session(&config.auth_db)?;
+    initialize_auth_database(&mut auth_session)?;
+
+    for database in main_databases(config, &mut auth_session)? {
+        let mut main_session = connector.session(&database)?;
+        initialize_main_database(&mut main_session, config.schema_profile, concurrently)?;
+    }
+
+    info!("Schemas are up to date.");
+    Ok(())
+}
+
+pub fn grant(config: &Config, connector: &Connector) -> Result<()> {
+    let mut postgres_session = connector.server()?;
+    let mut auth_session = connector.session(&config.auth_db)?;
+
+    grant_readwrite_permission(
+        &mut postgres_session,
+        &mut auth_session,
+        &config.auth_db,
+        &config.auth_readwrite_role,
+    )?;
+
+    for database in main_databases(config, &mut auth_session)? {
+        let mut main_session = connector.session(&database)?;
+        grant_main_permission(config, &mut postgres_session, &mut main_session, &database)?;
+    }
+
+    info!("Permissions granted.");
+    Ok(())
+}
+
+pub fn status(config: &Config, connector: &Connector) -> Result<()> {
+    let mut postgres_session = connector.server()?;
+
+    let mut auth_session = if database_exists(&mut postgres_session, &config.auth_db)? {
+        Some(connector.session(&config.auth_db)?)
+    } else {
+        None
+    };
+
+    let mut databases = vec![(config.auth_db.clone(), auth_migrations())];
+    // After a failed `init` the auth database may exist without its tables.
+    let main_dbs = if let Some(session) = auth_session.as_mut()
+        && !applied_migrations(session)?.is_empty()
+    {
+        main_databases(config, session)?
+    } else {
+        vec![config.main_db.clone()]
+    };
+    databases.extend(main_dbs.into_iter().map(|db| (db, main_migrations())));
+
+    println!("Databases:");
+    for (database, migrations) in &databases {
+        if !database_exists(&mut postgres_session, database)? {
+            println!("  {:<24} missing", database);
+            continue;
+        }
+
+        let mut session = connector.session(database)?;
+        let applied = applied_migrations(&mut session)?;
+        let current = applied.iter().map(|a| a.version).max().unwrap_or(0);
+        println!(
+            "  {:<24} schema version {}/{}",
+            database,
+            current,
+            latest_version(migrations)
+        );
+    }
+
+    println!("Roles:");
+    for role in config.ytx_roles() {
+        let state = if role_exists(&mut postgres_session, role)? {
+            "present"
+        } else {
+            "missing"
+        };
+        println!("  {:<24} {}", role, state);
+    }
+
+    println!("Workspaces:");
+    if let Some(session) = auth_session.as_mut()
+        && !applied_migrations(session)?.is_empty()
+    {
+        for (workspace, database) in workspace_databases(session)? {
+            println!("  {:<24} -> {}", workspace, database);
+        }
+    }
+
+    let journal = if database_exists(&mut postgres_session, &config.lock_database)? {
+        journal_entries(&mut connector.session(&config.lock_database)?)?
+    } else {
+        Vec::new()
+    };
+    if !journal.is_empty() {
+        println!("Last init run:");
+        for entry in journal {
+            let time = entry.finished_time.unwrap_or(entry.started_time);
+            println!("  {:<40} {:<8} {}", entry.step, entry.status, time);
+            if let Some(error) = entry.error {
+                println!("    {}", error);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+pub fn verify(config: &Config, connector: &Connector) -> Result<()> {
+    let mut postgres_session = connector.server()?;
+
+    let mut problems = Vec::new();
+
+    for role in config.ytx_roles() {
+        if !role_exists(&mut postgres_session, role)? {
+            problems.push(format!("Role {} is missing", role));
+        }
+    }
+
+    let mut databases = vec![(config.auth_db.clone(), auth_migrations())];
+    let mut main_dbs = vec![config.main_db.clone()];
+
+    if database_exists(&mut postgres_session, &config.auth_db)? {
+        let mut auth_session = connector.session(&config.auth_db)?;
+        if !applied_migrations(&mut auth_session)?.is_empty() {
+            let linked = workspace_database(&mut auth_session, &config.main_workspace)?;
+            if linked.as_deref() != Some(config.main_db.as_str()) {
+                problems.push(format!(
+                    "Workspace '{}' is not linked to database '{}'",
+                    config.main_workspace, config.main_db
+                ));
+            }
+            main_dbs = main_databases(config, &mut auth_session)?;
+        }
+    }
+
+    databases.extend(main_dbs.into_iter().map(|db| (db, main_migrations())));
+
+    for (database, migrations) in &databases {
+        if !database_exists(&mut postgres_session, database)? {
+            problems.push(format!("Database {} is missing", database));
+            continue;
+        }
+
+        let mut session = connector.session(database)?;
+        let applied = applied_migrations(&mut session)?;
+
+        if let Err(e) = check_applied(&applied, migrations) {
+            problems.push(format!("Database {}: {}", database, e));
+        }
+
+        for migration in migrations {
+            if !applied.iter().any(|a| a.version == migration.version) {
+                problems.push(format!(
+                    "Database {}: migration {} ({}) is pending",
+                    database, migration.version, migration.name
+                ));
+            }
+        }
+
+        // Compare against what the applied migrations should have produced,
+        // pending ones are already reported above.
+        let applied_steps: Vec<&Migration> = migrations
+            .iter()
+            .filter(|m| applied.iter().any(|a| a.version == m.version))
+            .collect();
+
+        if !applied_steps.is_empty() {
+            let expected = expected_shape(&mut session, &applied_steps)?;
+            let actual = actual_shape(&mut session)?;
+            for drift in compare_shapes(&expected, &actual) {
+                problems.push(format!("Database {}: {}", database, drift));
+            }
+
+            if *database != config.auth_db {
+                for problem in index_problems(&mut session)? {
+                    problems.push(format!("Database {}: {}", database, problem));
+                }
+            }
+
+            if config.schema_profile == SchemaProfile::Strict && *database != config.auth_db {
+                for problem in foreign_key_problems(&mut session)? {
+                    problems.push(format!("Database {}: {}", database, problem));
+                }
+            }
+        }
+    }
+
+    if !problems.is_empty() {
+        for problem in &problems {
+            error!("{}", problem);
+        }
+        bail!("Verification failed with {} problem(s)", problems.len());
+    }
+
+    info!("Verification passed.");
+    Ok(())
+}
+
+// Without a workspace the whole installation is removed. The confirmation
+// must name the target, so that a command copied between environments
+// cannot remove the wrong one.
+pub fn teardown(
+    config: &Config,
+    connector: &Connector,
+    workspace: Option<&str>,
+    confirm: Option<&str>,
+) -> Result<()> {
+    let target = workspace.unwrap_or(&config.auth_db);
+    if confirm != Some(target) && !connector.dry_run() {
+        match workspace {
+            Some(workspace) => bail!(
+                "Teardown drops the main database of workspace '{}', rerun with --confirm {}",
+                workspace,
+                workspace
+            ),
+            None => bail!(
+                "Teardown drops all main databases, the auth database `{}` and the YTX roles, rerun with --confirm {}",
+                config.auth_db,
+                config.auth_db
+            ),
+        }
+    }
+
+    match workspace {
+        Some(workspace) => teardown_workspace(config, connector, workspace),
+        None => teardown_all(config, connector),
+    }
+}
+
+fn teardown_workspace(config: &Config, connector: &Connector, workspace: &str) -> Result<()> {
+    let mut postgres_session = connector.server()?;
+    if !database_exists(&mut postgres_session, &config.auth_db)? {
+        bail!("Auth database `{}` does not exist", config.auth_db);
+    }
+
+    let mut auth_session = connector.session(&config.auth_db)?;
+    let Some(database) = workspace_database(&mut auth_session, workspace)? else {
+        bail!("Workspace '{}' is not registered", workspace);
+    };
+
+    let shared_with: Vec<String> = workspace_databases(&mut auth_session)?
+        .into_iter()
+        .filter(|(other, db)| other != workspace && *db == database)
+        .map(|(other, _)| other)
+        .collect();
+
+    if shared_with.is_empty() {
+        check_droppable(connector, &mut postgres_session, &database)?;
+        terminate_connections(&mut postgres_session, &database)?;
+        drop_database(&mut postgres_session, &database)?;
+    } else {
+        info!(
+            "Database {} is kept, workspace {} still uses it.",
+            database,
+            shared_with.join(", ")
+        );
+    }
+    delete_workspace_database(&mut auth_session, workspace)?;
+
+    info!("Workspace '{}' removed.", workspace);
+    Ok(())
+}
+
+fn teardown_all(config: &Config, connector: &Connector) -> Result<()> {
+    let mut postgres_session = connector.server()?;
+
+    // After a failed `init` the auth database may exist without its tables.
+    let mut databases = vec![config.main_db.clone()];
+    if database_exists(&mut postgres_session, &config.auth_db)? {
+        let mut auth_session = connector.session(&config.auth_db)?;
+        if !applied_migrations(&mut auth_session)?.is_empty() {
+            databases = main_databases(config, &mut auth_session)?;
+        }
+    }
+
+    // Checked before anything is dropped, so that a refusal leaves the
+    // installation intact.
+    for database in &databases {
+        check_droppable(connector, &mut postgres_session, database)?;
+    }
+    if config.auth_db == postgres_session.database() {
+        bail!(
+            "Auth database `{}` is the maintenance database, refusing to drop it",
+            config.auth_db
+        );
+    }
+    if database_exists(&mut postgres_session, &config.auth_db)? {
+        let mut auth_session = connector.session(&config.auth_db)?;
+        if !is_auth_database(&mut auth_session)? {
+            bail!(
+                "Database `{}` has no schema_migrations and ytx_workspace_database tables, refusing to drop it as the auth database",
+                config.auth_db
+            );
+        }
+    }
+    databases.push(config.auth_db.clone());
+
+    for database in &databases {
+        terminate_connections(&mut postgres_session, database)?;
+        drop_database(&mut postgres_session, database)?;
+    }
+
+    // The roles may still own objects or hold privileges in databases the
+    // tool does not manage, DROP ROLE fails until they are gone everywhere.
+    let mut roles = Vec::new();
+    for role in config.ytx_roles() {
+        if role_exists(&mut postgres_session, role)? {
+            roles.push(role);
+        }
+    }
+
+    if !roles.is_empty() {
+        for database in connectable_databases(&mut postgres_session)? {
+            if databases.contains(&database) {
+                continue;
+            }
+
+            let mut session = connector.session(&database)?;
+            drop_owned(&mut session, &roles, &config.postgres_role)?;
+        }
+    }
+
+    for role in config.ytx_roles() {
+        drop_role(&mut postgres_session, role)?;
+    }
+
+    if database_exists(&mut postgres_session, &config.lock_database)? {
+        connector
+            .session(&config.lock_database)?
+            .execute("DROP TABLE IF EXISTS ytx_init_journal")?;
+    }
+
+    info!("Installation removed.");
+    Ok(())
+}
+
+// Teardown drops only main databases the tool created, never the database
+// it is connected to or one registered for a workspace by mistake.
+fn check_droppable(
+    connector: &Connector,
+    postgres_session: &mut Session,
+    database: &str,
+) -> Result<()> {
+    if database == postgres_session.database() {
+        bail!(
+            "Database `{}` is the maintenance database, refusing to drop it",
+            database
+        );
+    }
+
+    if database_exists(postgres_session, database)? {
+        let mut session = connector.session(database)?;
+        if !is_managed_database(&mut session)? {
+            bail!(
+                "Database `{}` has no ytx_managed marker in ytx_meta, refusing to drop a database ytx-initdb did not create",
+                database
+            );
+        }
+    }
+
+    Ok(())
+}
+
+// Vault connects on its own, so only host, port, database and sslmode are
+// kept. Credentials are templated by the plugin.
+fn vault_connection_url(connection: &ConnectionString) -> Result<String> {
+    let config = &connection.config;
+    let host = match config.get_hosts().first() {
+        Some(Host::Tcp(host)) => host,
+        _ => bail!(
+            "PostgreSQL is reached through a Unix-domain socket, set VAULT_DATABASE_URL to an address Vault can connect to"
+        ),
+    };
+
+    let mut connection_url = format!(
+        "postgresql://{{{{username}}}}:{{{{password}}}}@{}:{}/{}",
+        host,
+        config.get_ports().first().copied().unwrap_or(5432),
+        config.get_dbname().unwrap_or("postgres")
+    );
+
+    if let Some(mode) = &connection.sslmode {
+        connection_url.push_str(&format!("?sslmode={}", mode));
+    }
+
+    Ok(connection_url)
+}
+
+// MAIN_DB first, followed by every other database registered for a workspace.
+fn main_databases(config: &Config, auth_session: &mut Session) -> Result<Vec<String>> {
+    let mut databases = vec![config.main_db.clone()];
+    for (_, database) in workspace_databases(auth_session)? {
+        if !databases.contains(&database) {
+            databases.push(database);
+        }
+    }
+
+    Ok(databases)
+}
+
+fn provision_main_database(
+    config: &Config,
+    postgres_session: &mut Session,
+    connector: &Connector,
+    database: &str,
+) -> Result<()> {
+    create_database(postgres_session, database)?;
+
+    let mut main_session = connector.session(database)?;
+    initialize_main_database(&mut main_session, config.schema_profile, false)?;
+
+    grant_main_permission(config, postgres_session, &mut main_session, database)
+}
+
+fn grant_main_permission(
+    config: &Config,
+    postgres_session: &mut Session,
+    main_session: &mut Session,
+    database: &str,
+) -> Result<()> {
+    grant_readonly_permission(
+        postgres_session,
+        main_session,
+        database,
+        &config.main_readonly_role,
+    )?;
+
+    grant_readwrite_permission(
+        postgres_session,
+        main_session,
+        database,
+        &config.main_readwrite_role,
+    )
+}
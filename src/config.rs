@@ -0,0 +1,505 @@
+use crate::connection::*;
+use crate::settings::*;
+use crate::vault::*;
+
+use anyhow::{Context, Result, bail};
+use log::info;
+use serde_json::Value;
+use std::path::PathBuf;
+use std::time::Duration;
+use unicode_xid::UnicodeXID;
+
+pub struct Config {
+    // Connection, from POSTGRES_URL completed with the PG* variables
+    pub postgres: ConnectionString,
+    // How long to wait for PostgreSQL and Vault to come up, zero to fail at once.
+    pub wait_timeout: Duration,
+    // How long to wait for another run to release the run lock.
+    pub lock_timeout: Duration,
+    // Database the run lock is taken in, the same for every run against the cluster.
+    pub lock_database: String,
+    // Set when a Vault token, token file or AppRole is configured, passwords then come from Vault.
+    pub vault: Option<Vault>,
+    pub postgres_secret: SecretRef,
+    pub ytx_secret: SecretRef,
+
+    // Database names
+    pub auth_db: String,
+    pub main_db: String,
+    pub main_workspace: String,
+
+    // Roles
+    pub postgres_role: String,
+    pub auth_readwrite_role: String,
+    pub main_readwrite_role: String,
+    pub main_readonly_role: String,
+
+    // Schema
+    pub schema_profile: SchemaProfile,
+
+    // Vault database secrets engine
+    pub vault_database: VaultDatabase,
+
+    // Further workspaces listed in the config file
+    pub workspaces: Vec<FileWorkspace>,
+}
+
+// Where and how `vault database` configures Vault's database secrets engine.
+pub struct VaultDatabase {
+    pub mount: String,
+    pub connection: String,
+    // URL Vault uses to reach PostgreSQL, derived from POSTGRES_URL when unset.
+    pub connection_url: Option<String>,
+    pub rotation_period: String,
+}
+
+// Optional parts of the main database schema on top of the migrations.
+#[derive(Clone, Copy, PartialEq)]
+pub enum SchemaProfile {
+    Standard,
+    // Adds foreign keys between entry, node, settlement and path tables.
+    Strict,
+}
+
+// sslrootcert/sslcert/sslkey of the connection string.
+#[derive(Clone)]
+pub struct SslFiles {
+    pub root_cert: Option<String>,
+    pub client_cert: Option<String>,
+    pub client_key: Option<String>,
+}
+
+// Secret path below the KV mount, optionally pinned to a version (KV v2 only).
+pub struct SecretRef {
+    pub path: String,
+    pub version: Option<u64>,
+}
+
+pub struct RolePasswords {
+    pub auth_readwrite: String,
+    pub main_readwrite: String,
+    pub main_readonly: String,
+}
+
+impl Config {
+    // Reads the environment, falling back to the config file loaded before,
+    // and takes the workspaces the file lists. They are validated where they
+    // are used, so that `workspace sync` can report a bad entry and go on.
+    pub fn with_workspaces(workspaces: Vec<FileWorkspace>) -> Result<Self> {
+        let mut config = Self::from_env()?;
+        config.workspaces = workspaces;
+        Ok(config)
+    }
+
+    pub fn from_env() -> Result<Self> {
+        let wait_timeout = read_seconds("WAIT_TIMEOUT")?;
+        let vault = read_vault(wait_timeout)?;
+        let kv_version = vault.as_ref().map(|v| v.kv_version());
+        let postgres = read_connection()?;
+
+        // A user named in the connection string or PGUSER is the superuser
+        // role unless POSTGRES_ROLE says otherwise.
+        let default_role = postgres.config.get_user().unwrap_or("postgres").to_string();
+
+        Ok(Self {
+            postgres,
+            wait_timeout,
+            lock_timeout: read_seconds("LOCK_TIMEOUT")?,
+            lock_database: read_value("LOCK_DATABASE")?,
+            vault,
+            postgres_secret: read_secret_ref("POSTGRES_SECRET", kv_version)?,
+            ytx_secret: read_secret_ref("YTX_SECRET", kv_version)?,
+
+            auth_db: read_value("AUTH_DB")?,
+            main_db: read_value("MAIN_DB")?,
+            main_workspace: read_workspace("MAIN_WORKSPACE")?,
+
+            postgres_role: read_value_with_default("POSTGRES_ROLE", &default_role)?,
+            auth_readwrite_role: read_value("AUTH_READWRITE_ROLE")?,
+            main_readwrite_role: read_value("MAIN_READWRITE_ROLE")?,
+            main_readonly_role: read_value("MAIN_READONLY_ROLE")?,
+
+            schema_profile: read_schema_profile()?,
+
+            vault_database: VaultDatabase {
+                mount: var("VAULT_DATABASE_MOUNT")
+                    .unwrap_or_default()
+                    .trim_matches('/')
+                    .to_string(),
+                connection: read_value("VAULT_DATABASE_CONNECTION")?,
+                connection_url: var("VAULT_DATABASE_URL").ok().filter(|v| !v.is_empty()),
+                rotation_period: var("VAULT_DATABASE_ROTATION_PERIOD").unwrap_or_default(),
+            },
+
+            workspaces: Vec::new(),
+        })
+    }
+
+    pub fn ytx_roles(&self) -> [&str; 3] {
+        [
+            &self.auth_readwrite_role,
+            &self.main_readonly_role,
+            &self.main_readwrite_role,
+        ]
+    }
+
+    // Vault takes priority over the environment when it is configured. None
+    // leaves the password to the connection string, PGPASSWORD or the password file.
+    pub fn postgres_password(&self) -> Result<Option<String>> {
+        match &self.vault {
+            Some(vault) => {
+                let pg_data = vault
+                    .read_data(&self.postgres_secret.path, self.postgres_secret.version)
+                    .context("Failed to read PostgreSQL superuser password from Vault")?;
+                get_vault_password(&pg_data, &self.postgres_role).map(Some)
+            }
+            None => Ok(var("POSTGRES_PASSWORD").ok().filter(|v| !v.is_empty())),
+        }
+    }
+
+    pub fn role_passwords(&self) -> Result<RolePasswords> {
+        match &self.vault {
+            Some(vault) => {
+                let ytx_data = vault
+                    .read_data(&self.ytx_secret.path, self.ytx_secret.version)
+                    .context("Failed to read YTX role passwords from Vault")?;
+                Ok(RolePasswords {
+                    auth_readwrite: get_vault_password(&ytx_data, &self.auth_readwrite_role)?,
+                    main_readwrite: get_vault_password(&ytx_data, &self.main_readwrite_role)?,
+                    main_readonly: get_vault_password(&ytx_data, &self.main_readonly_role)?,
+                })
+            }
+            None => Ok(RolePasswords {
+                auth_readwrite: var("AUTH_READWRITE_PASSWORD").unwrap_or_default(),
+                main_readwrite: var("MAIN_READWRITE_PASSWORD").unwrap_or_default(),
+                main_readonly: var("MAIN_READONLY_PASSWORD").unwrap_or_default(),
+            }),
+        }
+    }
+
+    // Generates passwords for the YTX roles that have none at YTX_SECRET_PATH and
+    // stores them with check-and-set, so that two concurrent bootstraps cannot
+    // overwrite each other. Roles in `existing_roles` must already have a password
+    // there, a generated one would not match the role in PostgreSQL.
+    pub fn bootstrap_role_passwords(
+        &self,
+        existing_roles: &[&str],
+        dry_run: bool,
+    ) -> Result<RolePasswords> {
+        let Some(vault) = &self.vault else {
+            bail!(
+                "Bootstrapping passwords into Vault requires POSTGRES_TOKEN, VAULT_TOKEN_FILE or an AppRole"
+            );
+        };
+
+        if self.ytx_secret.version.is_some() {
+            bail!("YTX_SECRET_VERSION pins the role passwords, unset it to bootstrap new ones");
+        }
+
+        let secret = vault
+            .read_secret(&self.ytx_secret.path)
+            .context("Failed to read YTX role passwords from Vault")?;
+        let (mut data, version) = match secret {
+            Some(secret) => (secret.data, secret.version),
+            None => Default::default(),
+        };
+
+        let mut generated = Vec::new();
+        for role in self.ytx_roles() {
+            if data.get(role).is_some_and(Value::is_string) {
+                continue;
+            }
+
+            if existing_roles.contains(&role) {
+                bail!(
+                    "Role {} already exists but has no password at {}, store its current password there first",
+                    role,
+                    self.ytx_secret.path
+                );
+            }
+
+            data.insert(role.to_string(), Value::String(generate_password()?));
+            generated.push(role);
+        }
+
+        if !generated.is_empty() {
+            if dry_run {
+                println!(
+                    "-- Vault: store generated passwords for {} at {} (cas={})",
+                    generated.join(", "),
+                    self.ytx_secret.path,
+                    version
+                );
+            } else {
+                vault
+                    .write_secret(&self.ytx_secret.path, &data, version)
+                    .with_context(|| {
+                        format!(
+                            "Failed to store generated passwords at {}, it may have been changed concurrently, rerun to pick them up",
+                            self.ytx_secret.path
+                        )
+                    })?;
+                info!(
+                    "Generated passwords for {} stored in Vault.",
+                    generated.join(", ")
+                );
+            }
+        }
+
+        let data = Value::Object(data);
+        Ok(RolePasswords {
+            auth_readwrite: get_vault_password(&data, &self.auth_readwrite_role)?,
+            main_readwrite: get_vault_password(&data, &self.main_readwrite_role)?,
+            main_readonly: get_vault_password(&data, &self.main_readonly_role)?,
+        })
+    }
+}
+
+// Values of POSTGRES_URL win over the libpq variables PGHOST, PGPORT, PGUSER,
+// PGPASSWORD, PGDATABASE, PGSSLMODE, PGSSLROOTCERT, PGSSLCERT, PGSSLKEY and
+// PGPASSFILE. POSTGRES_SSLROOTCERT/SSLCERT/SSLKEY win over both.
+fn read_connection() -> Result<ConnectionString> {
+    let read = |key: &str| var(key).ok().filter(|v| !v.is_empty());
+
+    let mut connection = parse_connection_string(&var("POSTGRES_URL").unwrap_or_default())?;
+    let config = &mut connection.config;
+
+    if config.get_hosts().is_empty() {
+        let hosts = read("PGHOST").unwrap_or_else(|| "localhost".to_string());
+        for host in hosts.split(',') {
+            match host.trim() {
+                path if path.starts_with('/') => config.host_path(path),
+                host => config.host(host),
+            };
+        }
+    }
+
+    if config.get_ports().is_empty()
+        && let Some(ports) = read("PGPORT")
+    {
+        for port in ports.split(',') {
+            config.port(
+                port.trim()
+                    .parse()
+                    .with_context(|| format!("Invalid PGPORT '{}'", ports))?,
+            );
+        }
+    }
+
+    if config.get_user().is_none()
+        && let Some(user) = read("PGUSER")
+    {
+        config.user(&user);
+    }
+
+    if config.get_password().is_none()
+        && let Some(password) = read("PGPASSWORD")
+    {
+        config.password(password);
+    }
+
+    // Without a database libpq connects to the one named after the user, the
+    // tool has always used `postgres`.
+    if config.get_dbname().is_none() {
+        config.dbname(&read("PGDATABASE").unwrap_or_else(|| "postgres".to_string()));
+    }
+
+    let ssl = &mut connection.ssl;
+    connection.sslmode = connection.sslmode.take().or(read("PGSSLMODE"));
+    ssl.root_cert = read("POSTGRES_SSLROOTCERT")
+        .or(ssl.root_cert.take())
+        .or(read("PGSSLROOTCERT"));
+    ssl.client_cert = read("POSTGRES_SSLCERT")
+        .or(ssl.client_cert.take())
+        .or(read("PGSSLCERT"));
+    ssl.client_key = read("POSTGRES_SSLKEY")
+        .or(ssl.client_key.take())
+        .or(read("PGSSLKEY"));
+
+    connection.passfile = connection
+        .passfile
+        .take()
+        .or(read("PGPASSFILE"))
+        .or_else(|| read("HOME").map(|home| format!("{}/.pgpass", home.trim_end_matches('/'))));
+
+    Ok(connection)
+}
+
+// A static token takes priority over a token file, which takes priority over
+// AppRole credentials.
+fn read_vault(wait_timeout: Duration) -> Result<Option<Vault>> {
+    let addr = var("VAULT_ADDR").unwrap_or_default();
+    let read = |key: &str| var(key).ok().filter(|v| !v.is_empty());
+
+    let auth = match (
+        read("POSTGRES_TOKEN"),
+        read("VAULT_TOKEN_FILE"),
+        read("VAULT_ROLE_ID"),
+        read("VAULT_SECRET_ID"),
+    ) {
+        (Some(token), _, _, _) => VaultAuth::Token(token),
+        (None, Some(path), _, _) => VaultAuth::TokenFile(path.into()),
+        (None, None, Some(role_id), Some(secret_id)) => VaultAuth::AppRole {
+            mount: read("VAULT_APPROLE_MOUNT").unwrap_or_default(),
+            role_id,
+            secret_id,
+        },
+        (None, None, Some(_), None) => bail!("VAULT_ROLE_ID is set but VAULT_SECRET_ID is missing"),
+        (None, None, None, Some(_)) => bail!("VAULT_SECRET_ID is set but VAULT_ROLE_ID is missing"),
+        (None, None, None, None) => return Ok(None),
+    };
+
+    let kv = KvMount {
+        path: read("VAULT_KV_MOUNT")
+            .unwrap_or_default()
+            .trim_matches('/')
+            .to_string(),
+        version: match read("VAULT_KV_VERSION").as_deref() {
+            None | Some("2") => KvVersion::V2,
+            Some("1") => KvVersion::V1,
+            Some(other) => bail!("Unsupported VAULT_KV_VERSION '{}', expected 1 or 2", other),
+        },
+    };
+
+    let path = |key: &str| read(key).map(PathBuf::from);
+    let options = VaultOptions {
+        namespace: read("VAULT_NAMESPACE"),
+        ca_cert: path("VAULT_CACERT"),
+        ca_path: path("VAULT_CAPATH"),
+        client_cert: path("VAULT_CLIENT_CERT"),
+        client_key: path("VAULT_CLIENT_KEY"),
+        timeout: read_seconds("VAULT_CLIENT_TIMEOUT")?,
+        wait_timeout,
+    };
+
+    if options.timeout.is_zero() {
+        bail!("VAULT_CLIENT_TIMEOUT must be greater than zero");
+    }
+
+    Ok(Some(Vault::new(&addr, auth, kv, &options)?))
+}
+
+// Reads `<prefix>_PATH` and `<prefix>_VERSION`.
+fn read_secret_ref(prefix: &str, kv_version: Option<KvVersion>) -> Result<SecretRef> {
+    let path = var(format!("{}_PATH", prefix)).unwrap_or_default();
+
+    let version_key = format!("{}_VERSION", prefix);
+    let version = match var(&version_key).ok().filter(|v| !v.is_empty()) {
+        Some(v) => Some(
+            v.parse::<u64>()
+                .ok()
+                .filter(|v| *v > 0)
+                .with_context(|| format!("{} must be a positive version number", version_key))?,
+        ),
+        None => None,
+    };
+
+    if version.is_some() && kv_version == Some(KvVersion::V1) {
+        bail!("{} requires the KV v2 secrets engine", version_key);
+    }
+
+    Ok(SecretRef {
+        path: path.trim_matches('/').to_string(),
+        version,
+    })
+}
+
+// Whole seconds, written as `30` or `30s`.
+fn read_seconds(key: &str) -> Result<Duration> {
+    let value = var(key).unwrap_or_default();
+    value
+        .trim_end_matches('s')
+        .parse()
+        .map(Duration::from_secs)
+        .with_context(|| format!("{} must be a number of seconds, got '{}'", key, value))
+}
+
+fn read_schema_profile() -> Result<SchemaProfile> {
+    match var("SCHEMA_PROFILE").unwrap_or_default().as_str() {
+        "standard" => Ok(SchemaProfile::Standard),
+        "strict" => Ok(SchemaProfile::Strict),
+        other => bail!(
+            "Unsupported SCHEMA_PROFILE '{}', expected 'standard' or 'strict'",
+            other
+        ),
+    }
+}
+
+fn read_value(key: &str) -> Result<String> {
+    read_value_with_default(key, "")
+}
+
+fn read_value_with_default(key: &str, default: &str) -> Result<String> {
+    let val = var(key).unwrap_or(default.to_string());
+    validate_value(key, &val)?;
+    Ok(val)
+}
+
+fn read_workspace(key: &str) -> Result<String> {
+    let val = var(key).unwrap_or_default();
+    validate_workspace(key, &val)?;
+    Ok(val)
+}
+
+// Database and role names are interpolated into SQL unquoted, so only plain
+// lowercase identifiers are accepted.
+pub fn validate_value(key: &str, val: &str) -> Result<()> {
+    if val.is_empty() {
+        bail!("Value for '{}' cannot be empty", key);
+    }
+
+    if val.len() > 63 {
+        bail!("Value for '{}' cannot be longer than 63 characters", key);
+    }
+
+    let mut chars = val.chars();
+    let first = chars.next().unwrap();
+
+    if !first.is_ascii_lowercase() {
+        bail!("Value for '{}' must start with a lowercase letter", key);
+    }
+
+    if !val
+        .chars()
+        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+    {
+        bail!(
+            "Value for '{}' can only contain lowercase letters, digits, and underscore",
+            key
+        );
+    }
+
+    Ok(())
+}
+
+pub fn validate_workspace(key: &str, val: &str) -> Result<()> {
+    if val.is_empty() {
+        bail!("Value for '{}' cannot be empty", key);
+    }
+
+    if val.len() > 63 {
+        bail!("Value for '{}' cannot be longer than 63 characters", key);
+    }
+
+    let mut chars = val.chars();
+    let first = chars.next().unwrap();
+
+    if !UnicodeXID::is_xid_start(first) {
+        bail!(
+            "Value for '{}' must start with a letter (Unicode allowed)",
+            key
+        );
+    }
+
+    if !val
+        .chars()
+        .all(|c| UnicodeXID::is_xid_continue(c) || c == '_')
+    {
+        bail!(
+            "Value for '{}' can only contain letters, digits, or underscore",
+            key
+        );
+    }
+
+    Ok(())
+}
@@ -7,5 +7,16 @@ pub const PURCHASE: &str = "purchase";
 
 pub const SECTIONS: &[&str] = &[FINANCE, STAKEHOLDER, ITEM, TASK, SALE, PURCHASE];
 
-pub const POSTGRES_SECRET_PATH: &str = "secret/data/postgres/postgres";
-pub const YTX_SECRET_PATH: &str = "secret/data/postgres/ytx";
+// Defaults of VAULT_KV_MOUNT, POSTGRES_SECRET_PATH and YTX_SECRET_PATH.
+pub const VAULT_KV_MOUNT: &str = "secret";
+pub const POSTGRES_SECRET_PATH: &str = "postgres/postgres";
+pub const YTX_SECRET_PATH: &str = "postgres/ytx";
+
+// Reported in pg_stat_activity for every connection of the tool.
+pub const APPLICATION_NAME: &str = "ytx-initdb";
+
+// Key of the session advisory lock held for the whole run, "ytx_init" in ASCII.
+pub const RUN_LOCK_KEY: i64 = 0x7974_785f_696e_6974;
+
+// Config file read from the working directory when --config is not given.
+pub const CONFIG_FILE: &str = "ytx-initdb.toml";
@@ -1,245 +1,434 @@
-use crate::constant::*;
-use crate::schema::*;
+use crate::config::*;
+use crate::connection::*;
+use crate::foreign_key::*;
+use crate::index::*;
+use crate::migration::*;
+use crate::scram::*;
 
 use anyhow::{Context, Result, bail};
-use postgres::Client;
-use url::Url;
-
-pub fn create_database(client: &mut Client, database: &str) -> Result<()> {
-    let exists: bool = client
-        .query_one(
-            "SELECT EXISTS(SELECT 1 FROM pg_database WHERE datname = $1)",
-            &[&database],
-        )
+use log::info;
+
+pub fn database_exists(session: &mut Session, database: &str) -> Result<bool> {
+    Ok(session
+        .query_opt("SELECT 1 FROM pg_database WHERE datname = $1", &[&database])
         .context("Failed to check if database exists")?
-        .get(0);
+        .is_some())
+}
+
+pub fn role_exists(session: &mut Session, role: &str) -> Result<bool> {
+    Ok(session
+        .query_opt("SELECT 1 FROM pg_roles WHERE rolname = $1", &[&role])
+        .context("Failed to check if role exists")?
+        .is_some())
+}
 
-    if !exists {
+pub fn create_database(session: &mut Session, database: &str) -> Result<()> {
+    if !database_exists(session, database)? {
         let create_sql = format!("CREATE DATABASE {}", database);
-        client
-            .execute(&create_sql, &[])
+        session
+            .execute(&create_sql)
             .with_context(|| format!("Failed to create database `{}`", database))?;
-        println!("Database {} created.", database);
+        info!("Database {} created.", database);
+    } else {
+        info!("Database {} already exists.", database);
+    }
+
+    Ok(())
+}
+
+pub fn drop_database(session: &mut Session, database: &str) -> Result<()> {
+    if database_exists(session, database)? {
+        session
+            .execute(&format!("DROP DATABASE {}", database))
+            .with_context(|| format!("Failed to drop database `{}`", database))?;
+        info!("Database {} dropped.", database);
     } else {
-        println!("Database {} already exists.", database);
+        info!("Database {} does not exist.", database);
     }
 
     Ok(())
 }
 
-pub fn create_role(client: &mut Client, role: &str, password: &str) -> Result<()> {
-    let exists: bool = client
-        .query_one(
-            "SELECT EXISTS(SELECT 1 FROM pg_roles WHERE rolname = $1)",
-            &[&role],
+// Main databases carry the `ytx_managed` marker from their first migration. A
+// database without any table is accepted too, e.g. one left by a failed `init`.
+pub fn is_managed_database(session: &mut Session) -> Result<bool> {
+    let managed = session
+        .query_opt("SELECT 1 WHERE to_regclass('ytx_meta') IS NOT NULL", &[])?
+        .is_some()
+        && session
+            .query_opt(
+                "SELECT 1 FROM ytx_meta WHERE key = 'ytx_managed' AND value",
+                &[],
+            )?
+            .is_some();
+    Ok(managed || !has_tables(session)?)
+}
+
+// The auth database is recognized by its tables, it has no ytx_meta marker.
+// A database without any table is accepted too, as for main databases.
+pub fn is_auth_database(session: &mut Session) -> Result<bool> {
+    let auth_tables = session
+        .query_opt(
+            "SELECT 1 WHERE to_regclass('schema_migrations') IS NOT NULL \
+             AND to_regclass('ytx_workspace_database') IS NOT NULL",
+            &[],
+        )?
+        .is_some();
+
+    Ok(auth_tables || !has_tables(session)?)
+}
+
+fn has_tables(session: &mut Session) -> Result<bool> {
+    Ok(session
+        .query_opt(
+            r#"
+            SELECT 1 FROM pg_class c
+            JOIN pg_namespace n ON n.oid = c.relnamespace
+            WHERE c.relkind IN ('r', 'p')
+              AND n.nspname NOT IN ('pg_catalog', 'information_schema')
+              AND n.nspname NOT LIKE 'pg_toast%'
+            LIMIT 1
+            "#,
+            &[],
         )
-        .context("Failed to check if role exists")?
-        .get(0);
+        .context("Failed to check for tables")?
+        .is_some())
+}
 
-    if !exists {
-        let row = client.query_one("SELECT quote_literal($1)", &[&password])?;
-        let escaped_password: String = row.get(0);
+// Disconnects every other session from the database, so that it can be dropped.
+pub fn terminate_connections(session: &mut Session, database: &str) -> Result<()> {
+    session
+        .execute(&format!(
+            "SELECT pg_terminate_backend(pid) FROM pg_stat_activity WHERE datname = {} AND pid <> pg_backend_pid()",
+            quote_literal(database)
+        ))
+        .with_context(|| format!("Failed to terminate connections to `{}`", database))
+}
+
+// Databases a session can be opened on, templates excluded.
+pub fn connectable_databases(session: &mut Session) -> Result<Vec<String>> {
+    let rows = session.query(
+        "SELECT datname FROM pg_database WHERE datallowconn AND NOT datistemplate ORDER BY datname",
+        &[],
+    )?;
+
+    Ok(rows.iter().map(|row| row.get(0)).collect())
+}
 
+pub fn create_role(session: &mut Session, role: &str, password: &str) -> Result<()> {
+    if !role_exists(session, role)? {
         let sql = format!(
             "CREATE ROLE {} WITH LOGIN PASSWORD {} NOCREATEDB NOCREATEROLE",
-            role, escaped_password
+            role,
+            quote_literal(&scram_verifier(password))
+        );
+        let shown = format!(
+            "CREATE ROLE {} WITH LOGIN PASSWORD '********' NOCREATEDB NOCREATEROLE",
+            role
         );
 
-        client
-            .execute(&sql, &[])
+        session
+            .execute_redacted(&sql, &shown)
             .with_context(|| format!("Failed to create role `{}`", role))?;
-        println!("Role {} created.", role);
+        info!("Role {} created.", role);
     } else {
-        println!("Role {} already exists.", role);
+        info!("Role {} already exists.", role);
     }
 
     Ok(())
 }
 
-pub fn initialize_main_database(client: &mut Client) -> Result<()> {
-    let mut transaction = client.transaction()?;
-
-    let mut sqls = Vec::new();
-    sqls.extend([
-        ytx_meta(),
-        global_config(),
-        f_node_table(),
-        s_node_table(),
-        i_node_table(),
-        t_node_table(),
-        f_entry_table(),
-        s_entry_table(),
-        t_entry_table(),
-        i_entry_table(),
-    ]);
-
-    for section in SECTIONS {
-        sqls.push(path_table(section));
-        sqls.push(insert_global_config(section));
+// Brings an existing role back to the state `create_role` would have created:
+// the expected password and LOGIN NOCREATEDB NOCREATEROLE NOSUPERUSER.
+// Every difference is reported, a missing role is created.
+pub fn reconcile_role(session: &mut Session, role: &str, password: &str) -> Result<()> {
+    let Some(row) = session.query_opt(
+        "SELECT rolcanlogin, rolcreatedb, rolcreaterole, rolsuper FROM pg_roles WHERE rolname = $1",
+        &[&role],
+    )?
+    else {
+        return create_role(session, role, password);
+    };
+
+    let mut attributes = Vec::new();
+    let mut changes = Vec::new();
+
+    for (attribute, expected, actual) in [
+        ("LOGIN", true, row.get::<_, bool>(0)),
+        ("CREATEDB", false, row.get(1)),
+        ("CREATEROLE", false, row.get(2)),
+        ("SUPERUSER", false, row.get(3)),
+    ] {
+        if expected != actual {
+            let prefix = if expected { "" } else { "NO" };
+            attributes.push(format!("{}{}", prefix, attribute));
+            changes.push(format!(
+                "{} {}",
+                attribute,
+                if expected { "granted" } else { "revoked" }
+            ));
+        }
+    }
+
+    // Only a real superuser can read pg_authid, the admin roles of managed
+    // services cannot. Their stored passwords cannot be checked and are set again.
+    let readable = session
+        .query_opt("SELECT has_table_privilege('pg_authid', 'SELECT')", &[])?
+        .is_some_and(|row| row.get(0));
+    let stored: Option<String> = if readable {
+        session
+            .query_opt(
+                "SELECT rolpassword FROM pg_authid WHERE rolname = $1",
+                &[&role],
+            )?
+            .and_then(|row| row.get(0))
+    } else {
+        None
+    };
+    let password_matches = stored
+        .as_deref()
+        .and_then(|verifier| verify_scram(password, verifier));
+
+    let mut sql = attributes.clone();
+    let mut shown = attributes;
+    match password_matches {
+        Some(true) => {}
+        Some(false) | None => {
+            sql.push(format!(
+                "PASSWORD {}",
+                quote_literal(&scram_verifier(password))
+            ));
+            shown.push("PASSWORD '********'".to_string());
+            changes.push(match (&stored, password_matches) {
+                (None, _) if !readable => {
+                    "password reset, the stored one cannot be read without superuser".to_string()
+                }
+                (None, _) => "password set".to_string(),
+                (Some(_), None) => {
+                    "password reset, the stored one is not SCRAM-SHA-256".to_string()
+                }
+                _ => "password updated".to_string(),
+            });
+        }
     }
 
-    for section in [SALE, PURCHASE] {
-        sqls.push(o_node_table(section));
-        sqls.push(o_entry_table(section));
-        sqls.push(o_settlement_table(section));
+    if changes.is_empty() {
+        info!("Role {} is up to date.", role);
+        return Ok(());
     }
 
-    sqls.push(insert_meta());
+    session
+        .execute_redacted(
+            &format!("ALTER ROLE {} WITH {}", role, sql.join(" ")),
+            &format!("ALTER ROLE {} WITH {}", role, shown.join(" ")),
+        )
+        .with_context(|| format!("Failed to reconcile role `{}`", role))?;
 
-    for sql in sqls {
-        if let Err(e) = transaction.execute(&sql, &[]) {
-            let _ = transaction.rollback();
-            bail!("Failed to execute SQL `{sql}`: {e}");
-        }
+    for change in changes {
+        info!("Role {}: {}.", role, change);
     }
 
-    transaction.commit()?;
     Ok(())
 }
 
-pub fn initialize_auth_database(client: &mut Client) -> Result<()> {
-    let mut transaction = client.transaction()?;
+pub fn set_role_password(session: &mut Session, role: &str, password: &str) -> Result<()> {
+    session
+        .execute_redacted(
+            &format!(
+                "ALTER ROLE {} WITH PASSWORD {}",
+                role,
+                quote_literal(&scram_verifier(password))
+            ),
+            &format!("ALTER ROLE {} WITH PASSWORD '********'", role),
+        )
+        .with_context(|| format!("Failed to change password of role `{}`", role))
+}
+
+// Objects owned by the roles go to `new_owner`, their privileges are revoked.
+// Both only affect the database of the session and shared objects.
+pub fn drop_owned(session: &mut Session, roles: &[&str], new_owner: &str) -> Result<()> {
+    let roles = roles.join(", ");
+    session
+        .execute(&format!("REASSIGN OWNED BY {} TO {}", roles, new_owner))
+        .with_context(|| {
+            format!(
+                "Failed to reassign objects of {} in `{}`",
+                roles,
+                session.database()
+            )
+        })?;
+    session
+        .execute(&format!("DROP OWNED BY {}", roles))
+        .with_context(|| {
+            format!(
+                "Failed to drop privileges of {} in `{}`",
+                roles,
+                session.database()
+            )
+        })
+}
 
-    let mut sqls = Vec::new();
-    sqls.extend([ytx_user(), ytx_role_workspace(), ytx_workspace_database()]);
+pub fn drop_role(session: &mut Session, role: &str) -> Result<()> {
+    if role_exists(session, role)? {
+        session
+            .execute(&format!("DROP ROLE {}", role))
+            .with_context(|| format!("Failed to drop role `{}`", role))?;
+        info!("Role {} dropped.", role);
+    } else {
+        info!("Role {} does not exist.", role);
+    }
 
-    for sql in sqls {
-        if let Err(e) = transaction.execute(&sql, &[]) {
-            let _ = transaction.rollback();
-            bail!("Failed to execute SQL `{sql}`: {e}");
-        }
+    Ok(())
+}
+
+pub fn initialize_main_database(
+    session: &mut Session,
+    profile: SchemaProfile,
+    concurrently: bool,
+) -> Result<()> {
+    migrate(session, &main_migrations())?;
+    apply_indexes(session, concurrently)?;
+
+    if profile == SchemaProfile::Strict {
+        apply_foreign_keys(session)?;
     }
 
-    transaction.commit()?;
     Ok(())
 }
 
+pub fn initialize_auth_database(session: &mut Session) -> Result<()> {
+    migrate(session, &auth_migrations())
+}
+
 pub fn grant_readonly_permission(
-    postgres_client: &mut Client,
-    client: &mut Client,
+    postgres_session: &mut Session,
+    session: &mut Session,
     database: &str,
     role: &str,
 ) -> Result<()> {
-    postgres_client.execute(
-        &format!("GRANT CONNECT ON DATABASE {} TO {}", database, role),
-        &[],
-    )?;
+    postgres_session.execute(&format!(
+        "GRANT CONNECT ON DATABASE {} TO {}",
+        database, role
+    ))?;
 
-    client.execute(&format!("GRANT USAGE ON SCHEMA public TO {}", role), &[])?;
+    session.execute(&format!("GRANT USAGE ON SCHEMA public TO {}", role))?;
 
-    client.execute(
-        &format!("GRANT SELECT ON ALL TABLES IN SCHEMA public TO {}", role),
-        &[],
-    )?;
+    session.execute(&format!(
+        "GRANT SELECT ON ALL TABLES IN SCHEMA public TO {}",
+        role
+    ))?;
 
-    client.execute(
-        &format!(
-            "ALTER DEFAULT PRIVILEGES IN SCHEMA public GRANT SELECT ON TABLES TO {}",
-            role
-        ),
-        &[],
-    )?;
+    session.execute(&format!(
+        "ALTER DEFAULT PRIVILEGES IN SCHEMA public GRANT SELECT ON TABLES TO {}",
+        role
+    ))?;
 
     Ok(())
 }
 
 pub fn grant_readwrite_permission(
-    postgres_client: &mut Client,
-    client: &mut Client,
+    postgres_session: &mut Session,
+    session: &mut Session,
     database: &str,
     role: &str,
 ) -> Result<()> {
-    postgres_client.execute(
-        &format!("GRANT CONNECT ON DATABASE {} TO {}", database, role),
-        &[],
-    )?;
+    postgres_session.execute(&format!(
+        "GRANT CONNECT ON DATABASE {} TO {}",
+        database, role
+    ))?;
 
-    client.execute(&format!("GRANT USAGE ON SCHEMA public TO {}", role), &[])?;
+    session.execute(&format!("GRANT USAGE ON SCHEMA public TO {}", role))?;
 
-    client.execute(
-        &format!(
-            "GRANT SELECT, INSERT, UPDATE, DELETE ON ALL TABLES IN SCHEMA public TO {}",
-            role
-        ),
-        &[],
-    )?;
+    session.execute(&format!(
+        "GRANT SELECT, INSERT, UPDATE, DELETE ON ALL TABLES IN SCHEMA public TO {}",
+        role
+    ))?;
 
-    client.execute(
-        &format!(
-            "GRANT USAGE, SELECT, UPDATE ON ALL SEQUENCES IN SCHEMA public TO {}",
-            role
-        ),
-        &[],
-    )?;
+    session.execute(&format!(
+        "GRANT USAGE, SELECT, UPDATE ON ALL SEQUENCES IN SCHEMA public TO {}",
+        role
+    ))?;
 
-    client.execute(
-        &format!(
+    session.execute(&format!(
             "ALTER DEFAULT PRIVILEGES IN SCHEMA public GRANT SELECT, INSERT, UPDATE, DELETE ON TABLES TO {}",
             role
-        ),
-        &[],
-    )?;
+        ))?;
 
-    client.execute(
-        &format!(
-            "ALTER DEFAULT PRIVILEGES IN SCHEMA public GRANT USAGE, SELECT, UPDATE ON SEQUENCES TO {}",
-            role
-        ),
-        &[],
-    )?;
+    session.execute(&format!(
+        "ALTER DEFAULT PRIVILEGES IN SCHEMA public GRANT USAGE, SELECT, UPDATE ON SEQUENCES TO {}",
+        role
+    ))?;
 
     Ok(())
 }
 
-pub fn replace_postgres_url(postgres_url: &str, new_db: &str) -> Result<String> {
-    let mut url = Url::parse(postgres_url)?;
-    url.set_path(&format!("/{}", new_db));
-    Ok(url.to_string())
-}
-
-pub fn build_url(base_url: &str, user: &str, password: &str) -> Result<String> {
-    let mut url = Url::parse(base_url)?;
-    url.set_username(user)
-        .map_err(|()| anyhow::anyhow!("Invalid username"))?;
-    url.set_password(Some(password))
-        .map_err(|()| anyhow::anyhow!("Invalid password"))?;
-    Ok(url.into())
-}
-
 pub fn insert_workspace_database(
-    client: &mut Client,
+    session: &mut Session,
     workspace: &str,
     database: &str,
 ) -> Result<()> {
-    let row = client.query_opt(
-        "SELECT database FROM ytx_workspace_database WHERE workspace = $1",
-        &[&workspace],
-    )?;
-
-    if let Some(row) = row {
-        let existing_db: String = row.get(0);
+    if let Some(existing_db) = workspace_database(session, workspace)? {
         if existing_db == database {
             return Ok(());
         } else {
             bail!(
-                "Workspace '{}' is already linked to a different database '{}', please check your .env configuration.",
+                "Workspace '{}' is already linked to a different database '{}', please check your configuration.",
                 workspace,
                 existing_db
             );
         }
     }
 
-    client.execute(
+    session.execute(&format!(
         r#"
         INSERT INTO ytx_workspace_database (workspace, database)
-        VALUES ($1, $2);
+        VALUES ({}, {});
     "#,
-        &[&workspace, &database],
-    )?;
-    println!(
+        quote_literal(workspace),
+        quote_literal(database)
+    ))?;
+    info!(
         "Workspace '{}' linked to database '{}'",
         workspace, database
     );
 
     Ok(())
 }
+
+pub fn delete_workspace_database(session: &mut Session, workspace: &str) -> Result<()> {
+    session.execute(&format!(
+        "DELETE FROM ytx_workspace_database WHERE workspace = {}",
+        quote_literal(workspace)
+    ))?;
+    info!("Workspace '{}' unlinked.", workspace);
+
+    Ok(())
+}
+
+pub fn workspace_database(session: &mut Session, workspace: &str) -> Result<Option<String>> {
+    let row = session.query_opt(
+        "SELECT database FROM ytx_workspace_database WHERE workspace = $1",
+        &[&workspace],
+    )?;
+
+    Ok(row.map(|row| row.get(0)))
+}
+
+pub fn workspace_databases(session: &mut Session) -> Result<Vec<(String, String)>> {
+    let rows = session.query(
+        "SELECT workspace, database FROM ytx_workspace_database ORDER BY workspace",
+        &[],
+    )?;
+
+    Ok(rows.iter().map(|row| (row.get(0), row.get(1))).collect())
+}
+
+// Same output as PostgreSQL's quote_literal().
+pub fn quote_literal(value: &str) -> String {
+    let quoted = value.replace('\'', "''");
+    if quoted.contains('\\') {
+        format!("E'{}'", quoted.replace('\\', "\\\\"))
+    } else {
+        format!("'{}'", quoted)
+    }
+}
@@ -0,0 +1,225 @@
+use crate::connection::*;
+use crate::migration::*;
+use crate::schema::*;
+
+use anyhow::{Context, Result};
+use postgres::GenericClient;
+
+// Scratch schema the expected model is built in, never committed.
+const EXPECTED_SCHEMA: &str = "ytx_verify_expected";
+
+#[derive(PartialEq)]
+pub struct ColumnShape {
+    pub name: String,
+    pub data_type: String,
+    pub numeric_precision: Option<i32>,
+    pub numeric_scale: Option<i32>,
+    pub datetime_precision: Option<i32>,
+    pub is_nullable: bool,
+    pub default: Option<String>,
+}
+
+pub struct ConstraintShape {
+    pub name: String,
+    pub kind: char,
+    pub definition: String,
+}
+
+pub struct TableShape {
+    pub name: String,
+    pub columns: Vec<ColumnShape>,
+    pub constraints: Vec<ConstraintShape>,
+}
+
+// Builds the schema the migrations produce by running them in a scratch
+// schema inside a transaction that is always rolled back.
+pub fn expected_shape(session: &mut Session, migrations: &[&Migration]) -> Result<Vec<TableShape>> {
+    let mut transaction = session.client()?.transaction()?;
+
+    transaction.batch_execute(&format!(
+        "CREATE SCHEMA {0}; SET LOCAL search_path TO {0};",
+        EXPECTED_SCHEMA
+    ))?;
+    transaction.batch_execute(&schema_migrations().create_sql())?;
+
+    for migration in migrations {
+        for sql in &migration.sqls {
+            transaction.batch_execute(sql).with_context(|| {
+                format!(
+                    "Failed to build expected schema from migration {} ({})",
+                    migration.version, migration.name
+                )
+            })?;
+        }
+    }
+
+    let shape = read_shape(&mut transaction, EXPECTED_SCHEMA)?;
+    transaction.rollback()?;
+    Ok(shape)
+}
+
+pub fn actual_shape(session: &mut Session) -> Result<Vec<TableShape>> {
+    read_shape(session.client()?, "public")
+}
+
+pub fn compare_shapes(expected: &[TableShape], actual: &[TableShape]) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    for table in expected {
+        let Some(found) = actual.iter().find(|t| t.name == table.name) else {
+            problems.push(format!("table {} is missing", table.name));
+            continue;
+        };
+
+        for column in &table.columns {
+            match found.columns.iter().find(|c| c.name == column.name) {
+                None => problems.push(format!("column {}.{} is missing", table.name, column.name)),
+                Some(c) if c != column => problems.push(format!(
+                    "column {}.{} is {}, expected {}",
+                    table.name,
+                    column.name,
+                    describe_column(c),
+                    describe_column(column)
+                )),
+                Some(_) => {}
+            }
+        }
+
+        for column in &found.columns {
+            if !table.columns.iter().any(|c| c.name == column.name) {
+                problems.push(format!(
+                    "column {}.{} is not part of the schema",
+                    table.name, column.name
+                ));
+            }
+        }
+
+        for constraint in &table.constraints {
+            match found.constraints.iter().find(|c| c.name == constraint.name) {
+                None => problems.push(format!(
+                    "{} constraint {} on {} is missing: {}",
+                    constraint_kind(constraint.kind),
+                    constraint.name,
+                    table.name,
+                    constraint.definition
+                )),
+                Some(c) if c.definition != constraint.definition => problems.push(format!(
+                    "{} constraint {} on {} is `{}`, expected `{}`",
+                    constraint_kind(constraint.kind),
+                    constraint.name,
+                    table.name,
+                    c.definition,
+                    constraint.definition
+                )),
+                Some(_) => {}
+            }
+        }
+    }
+
+    problems
+}
+
+fn read_shape(client: &mut impl GenericClient, schema: &str) -> Result<Vec<TableShape>> {
+    let mut tables: Vec<TableShape> = Vec::new();
+
+    // information_schema uses domain types, cast them to plain ones.
+    let columns = client
+        .query(
+            r#"
+            SELECT table_name::text, column_name::text, data_type::text,
+                   numeric_precision::int, numeric_scale::int, datetime_precision::int,
+                   is_nullable::text = 'YES', column_default::text
+            FROM information_schema.columns
+            WHERE table_schema = $1
+            ORDER BY table_name, ordinal_position
+            "#,
+            &[&schema],
+        )
+        .context("Failed to read information_schema.columns")?;
+
+    for row in columns {
+        let table: String = row.get(0);
+        let column = ColumnShape {
+            name: row.get(1),
+            data_type: row.get(2),
+            numeric_precision: row.get(3),
+            numeric_scale: row.get(4),
+            datetime_precision: row.get(5),
+            is_nullable: row.get(6),
+            default: row.get(7),
+        };
+
+        match tables.iter_mut().find(|t| t.name == table) {
+            Some(t) => t.columns.push(column),
+            None => tables.push(TableShape {
+                name: table,
+                columns: vec![column],
+                constraints: Vec::new(),
+            }),
+        }
+    }
+
+    let constraints = client
+        .query(
+            r#"
+            SELECT cl.relname::text, co.conname::text, co.contype::text,
+                   pg_get_constraintdef(co.oid)
+            FROM pg_constraint co
+            JOIN pg_class cl ON cl.oid = co.conrelid
+            JOIN pg_namespace ns ON ns.oid = cl.relnamespace
+            WHERE ns.nspname = $1 AND co.contype IN ('c', 'p', 'u')
+            ORDER BY cl.relname, co.conname
+            "#,
+            &[&schema],
+        )
+        .context("Failed to read constraints")?;
+
+    for row in constraints {
+        let table: String = row.get(0);
+        let kind: String = row.get(2);
+        if let Some(t) = tables.iter_mut().find(|t| t.name == table) {
+            t.constraints.push(ConstraintShape {
+                name: row.get(1),
+                kind: kind.chars().next().unwrap_or('c'),
+                definition: row.get(3),
+            });
+        }
+    }
+
+    Ok(tables)
+}
+
+fn describe_column(column: &ColumnShape) -> String {
+    let mut description = column.data_type.clone();
+
+    match (column.numeric_precision, column.numeric_scale) {
+        (Some(p), Some(s)) if column.data_type == "numeric" => {
+            description.push_str(&format!("({}, {})", p, s))
+        }
+        _ => {}
+    }
+
+    if let Some(p) = column.datetime_precision
+        && column.data_type.starts_with("timestamp")
+    {
+        description.push_str(&format!(" precision {}", p));
+    }
+
+    if !column.is_nullable {
+        description.push_str(" not null");
+    }
+
+    if let Some(default) = &column.default {
+        description.push_str(&format!(" default {}", default));
+    }
+
+    description
+}
+
+fn constraint_kind(kind: char) -> &'static str {
+    match kind {
+        'p' => "primary key",
+        'u' => "unique",
+        _ => "check",
+    }
+}
@@ -0,0 +1,113 @@
+use crate::connection::*;
+use crate::model::*;
+use crate::schema::*;
+
+use anyhow::{Context, Result};
+use log::{info, warn};
+
+// Adds the foreign keys of the strict profile. Constraints are created
+// NOT VALID, so new rows are checked right away, and validated only when no
+// existing row points at a missing target. Orphaned rows are reported and the
+// constraint stays NOT VALID until they are cleaned up and the run repeated.
+pub fn apply_foreign_keys(session: &mut Session) -> Result<()> {
+    for table in main_tables() {
+        for foreign_key in &table.foreign_keys {
+            let name = table.foreign_key_name(foreign_key);
+
+            let validated = match constraint_state(session, &table.name, &name)? {
+                Some(validated) => validated,
+                None => {
+                    session
+                        .execute(&table.foreign_key_sql(foreign_key))
+                        .with_context(|| format!("Failed to add foreign key {}", name))?;
+                    info!("Foreign key {} added.", name);
+                    false
+                }
+            };
+
+            if validated {
+                continue;
+            }
+
+            // In a dry run the migration creating the tables may only have
+            // been printed, rows can only be counted once they exist.
+            let countable = !session.dry_run()
+                && table_exists(session, &table.name)?
+                && table_exists(session, &foreign_key.references)?;
+            let orphans = if countable {
+                orphan_count(session, &table, foreign_key)?
+            } else {
+                0
+            };
+            if orphans > 0 {
+                warn!(
+                    "{} row(s) of {}.{} reference a missing row in {}, foreign key {} is left NOT VALID.",
+                    orphans, table.name, foreign_key.column, foreign_key.references, name
+                );
+                continue;
+            }
+
+            session
+                .execute(&format!(
+                    "ALTER TABLE {} VALIDATE CONSTRAINT {}",
+                    table.name, name
+                ))
+                .with_context(|| format!("Failed to validate foreign key {}", name))?;
+            info!("Foreign key {} validated.", name);
+        }
+    }
+
+    Ok(())
+}
+
+// Problems of the strict profile for `verify`: missing or unvalidated foreign keys.
+pub fn foreign_key_problems(session: &mut Session) -> Result<Vec<String>> {
+    let mut problems = Vec::new();
+
+    for table in main_tables() {
+        for foreign_key in &table.foreign_keys {
+            let name = table.foreign_key_name(foreign_key);
+            match constraint_state(session, &table.name, &name)? {
+                None => problems.push(format!("foreign key {} is missing", name)),
+                Some(false) => problems.push(format!(
+                    "foreign key {} is NOT VALID, {} row(s) of {}.{} reference a missing row in {}",
+                    name,
+                    orphan_count(session, &table, foreign_key)?,
+                    table.name,
+                    foreign_key.column,
+                    foreign_key.references
+                )),
+                Some(true) => {}
+            }
+        }
+    }
+
+    Ok(problems)
+}
+
+// None when the constraint does not exist, otherwise whether it is validated.
+fn constraint_state(session: &mut Session, table: &str, name: &str) -> Result<Option<bool>> {
+    let row = session.query_opt(
+        "SELECT convalidated FROM pg_constraint WHERE conname = $1 AND conrelid = to_regclass($2)",
+        &[&name, &table],
+    )?;
+
+    Ok(row.map(|r| r.get(0)))
+}
+
+fn table_exists(session: &mut Session, table: &str) -> Result<bool> {
+    Ok(session
+        .query_opt("SELECT 1 WHERE to_regclass($1) IS NOT NULL", &[&table])?
+        .is_some())
+}
+
+fn orphan_count(session: &mut Session, table: &Table, foreign_key: &ForeignKey) -> Result<i64> {
+    let sql = format!(
+        "SELECT count(*) FROM {0} t WHERE t.{1} IS NOT NULL \
+         AND NOT EXISTS (SELECT 1 FROM {2} r WHERE r.id = t.{1})",
+        table.name, foreign_key.column, foreign_key.references
+    );
+
+    let row = session.query_opt(&sql, &[])?;
+    Ok(row.map(|r| r.get(0)).unwrap_or(0))
+}
@@ -0,0 +1,63 @@
+use crate::connection::*;
+use crate::schema::*;
+
+use anyhow::{Context, Result};
+use log::info;
+
+// Creates the indexes declared in `schema.rs` that are missing. An index left
+// invalid by an interrupted CREATE INDEX CONCURRENTLY is dropped and rebuilt.
+// With `concurrently` writes to the tables are not blocked, which is meant
+// for upgrading a live database.
+pub fn apply_indexes(session: &mut Session, concurrently: bool) -> Result<()> {
+    let keyword = if concurrently { "CONCURRENTLY " } else { "" };
+
+    for table in main_tables() {
+        for index in &table.indexes {
+            let name = table.index_name(index);
+
+            match index_state(session, &name)? {
+                Some(true) => continue,
+                Some(false) => {
+                    session.execute(&format!("DROP INDEX {}IF EXISTS {}", keyword, name))?;
+                    info!("Invalid index {} dropped.", name);
+                }
+                None => {}
+            }
+
+            session
+                .execute(&table.index_sql(index, concurrently))
+                .with_context(|| format!("Failed to create index {}", name))?;
+            info!("Index {} created.", name);
+        }
+    }
+
+    Ok(())
+}
+
+// Missing and invalid indexes for `verify`.
+pub fn index_problems(session: &mut Session) -> Result<Vec<String>> {
+    let mut problems = Vec::new();
+
+    for table in main_tables() {
+        for index in &table.indexes {
+            let name = table.index_name(index);
+            match index_state(session, &name)? {
+                None => problems.push(format!("index {} is missing", name)),
+                Some(false) => problems.push(format!("index {} is invalid", name)),
+                Some(true) => {}
+            }
+        }
+    }
+
+    Ok(problems)
+}
+
+// None when the index does not exist, otherwise whether it is valid.
+fn index_state(session: &mut Session, name: &str) -> Result<Option<bool>> {
+    let row = session.query_opt(
+        "SELECT indisvalid FROM pg_index WHERE indexrelid = to_regclass($1)",
+        &[&name],
+    )?;
+
+    Ok(row.map(|r| r.get(0)))
+}
@@ -0,0 +1,146 @@
+use crate::connection::*;
+use crate::schema::*;
+
+use anyhow::{Context, Result, bail};
+use log::info;
+
+pub struct JournalEntry {
+    pub step: String,
+    pub status: String,
+    pub started_time: String,
+    pub finished_time: Option<String>,
+    pub error: Option<String>,
+}
+
+// Records each step of an `init` run in `ytx_init_journal`. A new run starts
+// with an empty journal, a resumed run skips the steps that are already done.
+pub struct Journal<'a> {
+    session: Session<'a>,
+    done: Vec<String>,
+    position: i32,
+    dry_run: bool,
+}
+
+impl<'a> Journal<'a> {
+    pub fn open(connector: &'a Connector, database: &str, resume: bool) -> Result<Self> {
+        let mut session = connector.session(database)?;
+
+        let done = if resume {
+            let entries = journal_entries(&mut session)?;
+            if entries.is_empty() {
+                bail!("No journal of an earlier run found, run `init` without --resume");
+            }
+            if entries.iter().all(|e| e.status == "done") {
+                info!("The last run completed, nothing to resume.");
+            }
+            entries
+                .into_iter()
+                .filter(|e| e.status == "done")
+                .map(|e| e.step)
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        // The plan of a dry run leaves the journal out.
+        if !connector.dry_run() {
+            session.execute(&ytx_init_journal().create_sql())?;
+            if !resume {
+                session.execute("TRUNCATE ytx_init_journal")?;
+            }
+        }
+
+        Ok(Self {
+            session,
+            done,
+            position: 0,
+            dry_run: connector.dry_run(),
+        })
+    }
+
+    // Runs `action` unless the step is done already, and records its outcome.
+    pub fn step(&mut self, step: &str, action: impl FnOnce() -> Result<()>) -> Result<()> {
+        self.position += 1;
+
+        if self.done.iter().any(|done| done == step) {
+            info!("Step '{}' is done, skipped.", step);
+            return Ok(());
+        }
+
+        if !self.dry_run {
+            self.session
+                .client()?
+                .execute(
+                    r#"
+                    INSERT INTO ytx_init_journal (step, position, status)
+                    VALUES ($1, $2, 'running')
+                    ON CONFLICT (step) DO UPDATE
+                    SET position = $2, status = 'running', started_time = now(),
+                        finished_time = NULL, error = NULL
+                    "#,
+                    &[&step, &self.position],
+                )
+                .context("Failed to write the init journal")?;
+        }
+
+        let result = action();
+
+        if !self.dry_run {
+            let (status, error) = match &result {
+                Ok(()) => ("done", None),
+                Err(e) => ("failed", Some(format!("{:#}", e))),
+            };
+            self.session
+                .client()?
+                .execute(
+                    r#"
+                    UPDATE ytx_init_journal
+                    SET status = $2, finished_time = now(), error = $3
+                    WHERE step = $1
+                    "#,
+                    &[&step, &status, &error],
+                )
+                .context("Failed to write the init journal")?;
+        }
+
+        result.with_context(|| {
+            format!(
+                "Step '{}' failed, fix the cause and rerun with `init --resume`",
+                step
+            )
+        })
+    }
+}
+
+// Steps of the last run in execution order, empty when there is no journal.
+pub fn journal_entries(session: &mut Session) -> Result<Vec<JournalEntry>> {
+    let exists = session
+        .query_opt(
+            "SELECT 1 WHERE to_regclass('ytx_init_journal') IS NOT NULL",
+            &[],
+        )?
+        .is_some();
+    if !exists {
+        return Ok(Vec::new());
+    }
+
+    let rows = session.query(
+        r#"
+        SELECT step, status, started_time::text, finished_time::text, error
+        FROM ytx_init_journal
+        ORDER BY position
+        "#,
+        &[],
+    )?;
+
+    Ok(rows
+        .iter()
+        .map(|row| JournalEntry {
+            step: row.get(0),
+            status: row.get(1),
+            started_time: row.get(2),
+            finished_time: row.get(3),
+            error: row.get(4),
+        })
+        .collect())
+}
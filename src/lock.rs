@@ -0,0 +1,89 @@
+use crate::connection::*;
+use crate::constant::*;
+
+use anyhow::{Context, Result, bail};
+use log::{debug, info};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+// Holds the run lock until dropped, PostgreSQL releases it with the connection.
+pub struct RunLock<'a> {
+    _session: Session<'a>,
+}
+
+// Takes the advisory lock on a connection of its own, so that a second run
+// waits instead of racing through the existence checks. Advisory locks are
+// per database, so every run locks in `database` whatever its maintenance
+// database is. Gives up after `timeout` and names the holder.
+pub fn lock_run<'a>(
+    connector: &'a Connector,
+    database: &str,
+    timeout: Duration,
+) -> Result<RunLock<'a>> {
+    let mut session = connector
+        .session(database)
+        .with_context(|| format!("Failed to connect to lock database `{}`", database))?;
+    let deadline = Instant::now() + timeout;
+    let mut reported = None;
+
+    loop {
+        let locked: bool = session
+            .client()?
+            .query_one("SELECT pg_try_advisory_lock($1)", &[&RUN_LOCK_KEY])
+            .context("Failed to take the run lock")?
+            .get(0);
+        if locked {
+            debug!("Run lock acquired.");
+            return Ok(RunLock { _session: session });
+        }
+
+        let holder = lock_holder(&mut session)?;
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            match holder {
+                Some((pid, application)) => bail!(
+                    "Another run holds the lock: PID {} (application_name '{}'), gave up after {}s",
+                    pid,
+                    application,
+                    timeout.as_secs()
+                ),
+                None => bail!(
+                    "Another run holds the lock, gave up after {}s",
+                    timeout.as_secs()
+                ),
+            }
+        }
+
+        if holder != reported {
+            if let Some((pid, application)) = &holder {
+                info!(
+                    "Waiting for the lock held by PID {} (application_name '{}').",
+                    pid, application
+                );
+            }
+            reported = holder;
+        }
+
+        sleep(POLL_INTERVAL.min(left));
+    }
+}
+
+// PID and application_name of the session holding the lock, None when it was
+// released in the meantime.
+fn lock_holder(session: &mut Session) -> Result<Option<(i32, String)>> {
+    let row = session.query_opt(
+        r#"
+        SELECT a.pid, coalesce(a.application_name, '')
+        FROM pg_locks l
+        JOIN pg_stat_activity a ON a.pid = l.pid
+        WHERE l.locktype = 'advisory' AND l.granted AND l.objsubid = 1
+          AND l.database = (SELECT oid FROM pg_database WHERE datname = current_database())
+          AND ((l.classid::bigint << 32) | l.objid::bigint) = $1
+        "#,
+        &[&RUN_LOCK_KEY],
+    )?;
+
+    Ok(row.map(|row| (row.get(0), row.get(1))))
+}
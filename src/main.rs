@@ -1,200 +1,132 @@
+mod cli;
+mod command;
+mod config;
+mod connection;
 mod constant;
 mod database;
+mod drift;
+mod foreign_key;
+mod index;
+mod journal;
+mod lock;
+mod migration;
+mod model;
+mod pgpass;
 mod schema;
-
-use crate::constant::*;
-use crate::database::*;
-use anyhow::{Context, Result, bail};
-use dotenvy::dotenv;
-use reqwest::blocking::Client;
-use reqwest::header::{AUTHORIZATION, HeaderMap, HeaderValue};
-use serde_json::Value;
-use std::env::var;
-use unicode_xid::UnicodeXID;
+mod scram;
+mod settings;
+mod vault;
+mod wait;
+
+use crate::cli::*;
+use crate::config::Config;
+use crate::connection::Connector;
+use crate::constant::CONFIG_FILE;
+use anyhow::{Context, Result};
+use clap::Parser;
+use log::LevelFilter;
+use std::io::Write;
+use std::path::Path;
 
 fn main() -> Result<()> {
-    dotenv().ok();
-
-    // Connection
-    let postgres_url =
-        var("POSTGRES_URL").unwrap_or_else(|_| "postgres://localhost:5432/postgres".to_string());
-    let vault_addr = var("VAULT_ADDR").unwrap_or_else(|_| "http://127.0.0.1:8200".to_string());
-
-    // Database names
-    let auth_db = read_value_with_default("AUTH_DB", "ytx_auth")?;
-    let main_db = read_value_with_default("MAIN_DB", "ytx_main")?;
-    let main_workspace = read_workspace_with_default("MAIN_WORKSPACE", "ytx_workspace")?;
-
-    // Roles
-    let postgres_role = read_value_with_default("POSTGRES_ROLE", "postgres")?;
-    let auth_readwrite_role = read_value_with_default("AUTH_READWRITE_ROLE", "ytx_auth_readwrite")?;
-    let main_readwrite_role = read_value_with_default("MAIN_READWRITE_ROLE", "ytx_main_readwrite")?;
-    let main_readonly_role = read_value_with_default("MAIN_READONLY_ROLE", "ytx_main_readonly")?;
-
-    // Passwords (can be overridden by Vault)
-    let mut postgres_password = var("POSTGRES_PASSWORD").unwrap_or_default();
-    let mut auth_readwrite_password = var("AUTH_READWRITE_PASSWORD").unwrap_or_default();
-    let mut main_readwrite_password = var("MAIN_READWRITE_PASSWORD").unwrap_or_default();
-    let mut main_readonly_password = var("MAIN_READONLY_PASSWORD").unwrap_or_default();
-
-    if let Ok(postgres_token) = var("POSTGRES_TOKEN") {
-        if !postgres_token.is_empty() {
-            let pg_data = read_vault_data(&vault_addr, &postgres_token, POSTGRES_SECRET_PATH)
-                .context("Failed to read PostgreSQL superuser password from Vault")?;
-            postgres_password = get_vault_password(&pg_data, &postgres_role)?;
+    let cli = Cli::parse();
 
-            let ytx_data = read_vault_data(&vault_addr, &postgres_token, YTX_SECRET_PATH)
-                .context("Failed to read YTX role passwords from Vault")?;
-            auth_readwrite_password = get_vault_password(&ytx_data, &auth_readwrite_role)?;
-            main_readonly_password = get_vault_password(&ytx_data, &main_readonly_role)?;
-            main_readwrite_password = get_vault_password(&ytx_data, &main_readwrite_role)?;
+    match &cli.env_file {
+        Some(path) => {
+            dotenvy::from_path(path)
+                .with_context(|| format!("Failed to load env file `{}`", path.display()))?;
+        }
+        None => {
+            dotenvy::dotenv().ok();
         }
     }
 
-    let full_postgres_url = build_url(&postgres_url, &postgres_role, &postgres_password)?;
-    let mut postgres_client = postgres::Client::connect(&full_postgres_url, postgres::NoTls)
-        .context("Failed to connect to PostgreSQL server")?;
-
-    create_database(&mut postgres_client, &auth_db)?;
-    create_database(&mut postgres_client, &main_db)?;
-
-    create_role(
-        &mut postgres_client,
-        &auth_readwrite_role,
-        &auth_readwrite_password,
-    )?;
-
-    create_role(
-        &mut postgres_client,
-        &main_readonly_role,
-        &main_readonly_password,
-    )?;
-
-    create_role(
-        &mut postgres_client,
-        &main_readwrite_role,
-        &main_readwrite_password,
-    )?;
-
-    let auth_url = replace_postgres_url(&full_postgres_url, &auth_db)?;
-    let mut auth_client = postgres::Client::connect(&auth_url, postgres::NoTls)?;
-
-    initialize_auth_database(&mut auth_client)?;
-    insert_workspace_database(&mut auth_client, &main_workspace, &main_db)?;
-
-    let main_url = replace_postgres_url(&full_postgres_url, &main_db)?;
-    let mut main_client = postgres::Client::connect(&main_url, postgres::NoTls)?;
-    initialize_main_database(&mut main_client)?;
-
-    grant_readonly_permission(
-        &mut postgres_client,
-        &mut main_client,
-        &main_db,
-        &main_readonly_role,
-    )?;
-
-    grant_readwrite_permission(
-        &mut postgres_client,
-        &mut main_client,
-        &main_db,
-        &main_readwrite_role,
-    )?;
-
-    grant_readwrite_permission(
-        &mut postgres_client,
-        &mut auth_client,
-        &auth_db,
-        &auth_readwrite_role,
-    )?;
-
-    Ok(())
-}
-
-fn read_vault_data(vault_addr: &str, token: &str, secret_path: &str) -> Result<Value> {
-    let url = format!("{}/v1/{}", vault_addr.trim_end_matches('/'), secret_path);
-    let mut headers = HeaderMap::new();
-    headers.insert(
-        AUTHORIZATION,
-        HeaderValue::from_str(&format!("Bearer {}", token))?,
-    );
-
-    let resp = Client::new().get(&url).headers(headers).send()?;
-    if !resp.status().is_success() {
-        anyhow::bail!("HTTP error {}", resp.status());
-    }
-
-    let json: Value = resp.json()?;
-    Ok(json["data"]["data"].clone())
-}
-
-fn get_vault_password(data: &serde_json::Value, key: &str) -> Result<String> {
-    data.get(key)
-        .and_then(|v| v.as_str())
-        .map(|s| s.to_string())
-        .ok_or_else(|| anyhow::anyhow!("Vault key '{}' not found or not a string", key))
-}
-
-fn read_value_with_default(key: &str, default: &str) -> Result<String> {
-    let val = var(key).unwrap_or(default.to_string());
-
-    if val.is_empty() {
-        bail!("Value for '{}' cannot be empty", key);
-    }
-
-    if val.len() > 63 {
-        bail!("Value for '{}' cannot be longer than 63 characters", key);
-    }
-
-    let mut chars = val.chars();
-    let first = chars.next().unwrap();
-
-    if !first.is_ascii_lowercase() {
-        bail!("Value for '{}' must start with a lowercase letter", key);
+    // A dry run prints the plan to stdout, progress messages would describe
+    // changes that are not made.
+    init_logger(cli.verbose, cli.quiet || cli.dry_run);
+
+    let workspaces = match &cli.config {
+        Some(path) => settings::load_file(path)?,
+        None if Path::new(CONFIG_FILE).exists() => settings::load_file(Path::new(CONFIG_FILE))?,
+        None => Vec::new(),
+    };
+
+    let command = cli.command.unwrap_or(Command::Init {
+        bootstrap_vault: false,
+        resume: false,
+    });
+
+    // Printed before validation, so that a broken configuration can be inspected.
+    if let Command::Config = command {
+        settings::print_settings(&workspaces);
+        return Ok(());
     }
 
-    if !val
-        .chars()
-        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
-    {
-        bail!(
-            "Value for '{}' can only contain lowercase letters, digits, and underscore",
-            key
-        );
+    let config = Config::with_workspaces(workspaces)?;
+    let connector = Connector::new(&config, cli.dry_run)?;
+
+    // Read-only commands and dry runs do not change anything and run alongside others.
+    let _lock = match command {
+        Command::Status | Command::Verify => None,
+        _ if cli.dry_run => None,
+        _ => Some(lock::lock_run(
+            &connector,
+            &config.lock_database,
+            config.lock_timeout,
+        )?),
+    };
+
+    match command {
+        Command::Init {
+            bootstrap_vault,
+            resume,
+        } => command::init(&config, &connector, bootstrap_vault, resume),
+        Command::Workspace { command } => match command {
+            WorkspaceCommand::Add { name, database } => {
+                command::add_workspace(&config, &connector, &name, database.as_deref())
+            }
+            WorkspaceCommand::Sync { manifest } => {
+                command::sync_workspaces(&config, &connector, manifest.as_deref())
+            }
+        },
+        Command::Vault { command } => match command {
+            VaultCommand::Database => command::configure_vault_database(&config, &connector),
+        },
+        Command::RotatePassword { role } => command::rotate_password(&config, &connector, &role),
+        Command::Reconcile => command::reconcile(&config, &connector),
+        Command::Migrate { concurrently } => command::migrate(&config, &connector, concurrently),
+        Command::Grant => command::grant(&config, &connector),
+        Command::Status => command::status(&config, &connector),
+        Command::Verify => command::verify(&config, &connector),
+        Command::Config => unreachable!("handled before connecting"),
+        Command::Teardown {
+            workspace,
+            all: _,
+            confirm,
+        } => command::teardown(
+            &config,
+            &connector,
+            workspace.as_deref(),
+            confirm.as_deref(),
+        ),
     }
-
-    Ok(val)
 }
 
-fn read_workspace_with_default(key: &str, default: &str) -> Result<String> {
-    let val = var(key).unwrap_or(default.to_string());
-
-    if val.is_empty() {
-        bail!("Value for '{}' cannot be empty", key);
-    }
-
-    if val.len() > 63 {
-        bail!("Value for '{}' cannot be longer than 63 characters", key);
-    }
-
-    let mut chars = val.chars();
-    let first = chars.next().unwrap();
-
-    if !UnicodeXID::is_xid_start(first) {
-        bail!(
-            "Value for '{}' must start with a letter (Unicode allowed)",
-            key
-        );
-    }
-
-    if !val
-        .chars()
-        .all(|c| UnicodeXID::is_xid_continue(c) || c == '_')
-    {
-        bail!(
-            "Value for '{}' can only contain letters, digits, or underscore",
-            key
-        );
-    }
-
-    Ok(val)
+fn init_logger(verbose: u8, quiet: bool) {
+    let level = match (quiet, verbose) {
+        (true, _) => LevelFilter::Warn,
+        (false, 0) => LevelFilter::Info,
+        (false, 1) => LevelFilter::Debug,
+        (false, _) => LevelFilter::Trace,
+    };
+
+    // Only this crate's messages follow the verbosity flags, dependencies stay quiet.
+    env_logger::Builder::new()
+        .filter_level(LevelFilter::Warn)
+        .filter_module("ytx_initdb", level)
+        .format(|buf, record| match record.level() {
+            log::Level::Info => writeln!(buf, "{}", record.args()),
+            level => writeln!(buf, "{}: {}", level.as_str().to_lowercase(), record.args()),
+        })
+        .init();
 }
@@ -0,0 +1,270 @@
+use crate::connection::*;
+use crate::constant::*;
+use crate::model::*;
+use crate::schema::*;
+
+use anyhow::{Context, Result, bail};
+use log::info;
+use sha2::{Digest, Sha256};
+
+// Released migrations are append-only: never edit the SQL of a version that
+// has shipped, add a new version instead. The checksum recorded in
+// `schema_migrations` is used to detect edited steps.
+pub struct Migration {
+    pub version: i32,
+    pub name: &'static str,
+    pub sqls: Vec<String>,
+}
+
+impl Migration {
+    pub fn checksum(&self) -> String {
+        let mut hasher = Sha256::new();
+        for sql in &self.sqls {
+            hasher.update(normalize_sql(sql).as_bytes());
+            hasher.update(b"\n");
+        }
+
+        hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+}
+
+pub struct AppliedMigration {
+    pub version: i32,
+    pub name: String,
+    pub checksum: String,
+}
+
+pub fn main_migrations() -> Vec<Migration> {
+    let mut baseline = Vec::new();
+    baseline.extend(
+        [
+            ytx_meta(),
+            global_config(),
+            f_node_table(),
+            s_node_table(),
+            i_node_table(),
+            t_node_table(),
+            f_entry_table(),
+            s_entry_table(),
+            t_entry_table(),
+            i_entry_table(),
+        ]
+        .iter()
+        .map(Table::create_sql),
+    );
+
+    for section in SECTIONS {
+        baseline.push(path_table(section).create_sql());
+        baseline.push(insert_global_config(section));
+    }
+
+    for section in [SALE, PURCHASE] {
+        baseline.push(o_node_table(section).create_sql());
+        baseline.push(o_entry_table(section).create_sql());
+        baseline.push(o_settlement_table(section).create_sql());
+    }
+
+    baseline.push(insert_meta());
+
+    vec![Migration {
+        version: 1,
+        name: "baseline",
+        sqls: baseline,
+    }]
+}
+
+pub fn auth_migrations() -> Vec<Migration> {
+    vec![Migration {
+        version: 1,
+        name: "baseline",
+        sqls: [ytx_user(), ytx_role_workspace(), ytx_workspace_database()]
+            .iter()
+            .map(Table::create_sql)
+            .collect(),
+    }]
+}
+
+pub fn migrate(session: &mut Session, migrations: &[Migration]) -> Result<()> {
+    session
+        .execute(&schema_migrations().create_sql())
+        .context("Failed to create schema_migrations table")?;
+
+    let applied = applied_migrations(session)?;
+    check_applied(&applied, migrations)?;
+
+    for migration in migrations {
+        if applied.iter().any(|a| a.version == migration.version) {
+            continue;
+        }
+
+        // The table lock serializes concurrent runs against the same database,
+        // a run that waited fails on the primary key instead of applying twice.
+        let mut sqls = vec!["LOCK TABLE schema_migrations IN EXCLUSIVE MODE".to_string()];
+        sqls.extend(migration.sqls.iter().cloned());
+        sqls.push(format!(
+            "INSERT INTO schema_migrations (version, name, checksum) VALUES ({}, '{}', '{}')",
+            migration.version,
+            migration.name,
+            migration.checksum()
+        ));
+
+        session.execute_all(&sqls).with_context(|| {
+            format!(
+                "Migration {} ({}) failed on database `{}`",
+                migration.version,
+                migration.name,
+                session.database()
+            )
+        })?;
+
+        info!(
+            "Migration {} ({}) applied.",
+            migration.version, migration.name
+        );
+    }
+
+    Ok(())
+}
+
+pub fn latest_version(migrations: &[Migration]) -> i32 {
+    migrations.iter().map(|m| m.version).max().unwrap_or(0)
+}
+
+pub fn applied_migrations(session: &mut Session) -> Result<Vec<AppliedMigration>> {
+    let tracked = session
+        .query_opt(
+            "SELECT 1 WHERE to_regclass('schema_migrations') IS NOT NULL",
+            &[],
+        )?
+        .is_some();
+
+    if !tracked {
+        return Ok(Vec::new());
+    }
+
+    let rows = session
+        .query(
+            "SELECT version, name, checksum FROM schema_migrations ORDER BY version",
+            &[],
+        )
+        .context("Failed to read schema_migrations")?;
+
+    Ok(rows
+        .iter()
+        .map(|row| AppliedMigration {
+            version: row.get(0),
+            name: row.get(1),
+            checksum: row.get(2),
+        })
+        .collect())
+}
+
+pub fn check_applied(applied: &[AppliedMigration], migrations: &[Migration]) -> Result<()> {
+    for a in applied {
+        match migrations.iter().find(|m| m.version == a.version) {
+            Some(m) if m.checksum() != a.checksum => bail!(
+                "Migration {} ({}) was modified after it was applied (checksum mismatch)",
+                a.version,
+                a.name
+            ),
+            Some(_) => {}
+            None => bail!(
+                "Database has migration {} ({}) which this version of ytx-initdb does not know, please upgrade",
+                a.version,
+                a.name
+            ),
+        }
+    }
+
+    Ok(())
+}
+
+// Strip comments and insignificant whitespace so that reformatting a
+// statement does not change its checksum.
+fn normalize_sql(sql: &str) -> String {
+    let mut normalized = String::new();
+
+    for line in sql.lines() {
+        let code = line.split("--").next().unwrap_or_default();
+        for token in code.split_whitespace() {
+            let last = normalized.chars().last();
+            let first = token.chars().next();
+            let glued = matches!(last, None | Some('(' | ',' | ';'))
+                || matches!(first, Some('(' | ')' | ',' | ';'));
+
+            if !glued {
+                normalized.push(' ');
+            }
+            normalized.push_str(token);
+        }
+    }
+
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn migration(sql: &str) -> Migration {
+        Migration {
+            version: 1,
+            name: "test",
+            sqls: vec![sql.to_string()],
+        }
+    }
+
+    #[test]
+    fn normalize_sql_ignores_whitespace_and_comments() {
+        let compact = "CREATE TABLE t (id UUID PRIMARY KEY, name TEXT);";
+        let reformatted = r#"
+            -- the table
+            CREATE TABLE t (
+                id   UUID PRIMARY KEY, -- key
+                name TEXT
+            ) ;
+        "#;
+
+        assert_eq!(normalize_sql(reformatted), normalize_sql(compact));
+        assert_eq!(
+            normalize_sql(compact),
+            "CREATE TABLE t(id UUID PRIMARY KEY,name TEXT);"
+        );
+    }
+
+    #[test]
+    fn reformatting_keeps_the_checksum() {
+        let compact = migration("INSERT INTO ytx_meta (key, value) VALUES ('ytx_managed', TRUE);");
+        let reformatted = migration(
+            "\n    INSERT INTO ytx_meta (key, value)\n    VALUES ('ytx_managed', TRUE)\n    ;\n",
+        );
+
+        assert_eq!(compact.checksum(), reformatted.checksum());
+    }
+
+    #[test]
+    fn changed_sql_changes_the_checksum() {
+        let before = migration("CREATE TABLE t (id UUID);");
+        let after = migration("CREATE TABLE t (id TEXT);");
+
+        assert_ne!(before.checksum(), after.checksum());
+    }
+
+    // Released migrations must never change, `migrate` would refuse every
+    // installed database.
+    #[test]
+    fn released_checksums_are_unchanged() {
+        assert_eq!(
+            main_migrations()[0].checksum(),
+            "2b73a5c4dbf1630fdbd7fb31168ee8e45e56822e31fb57dc5d42ff690de1a95b"
+        );
+        assert_eq!(
+            auth_migrations()[0].checksum(),
+            "dda636071e045c7d718a6092b6909136d64154533afe9d25306be400ec5856d6"
+        );
+    }
+}
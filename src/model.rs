@@ -0,0 +1,263 @@
+// Typed description of the YTX tables. `schema.rs` defines the tables with
+// these types and the DDL is rendered from them, so migrations, verification
+// and documentation share one definition.
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ColumnType {
+    Uuid,
+    Text,
+    Integer,
+    Boolean,
+    Numeric(u8, u8),
+    Timestamptz(u8),
+}
+
+// ON DELETE action of a foreign key.
+#[derive(Clone, Copy, PartialEq)]
+pub enum OnDelete {
+    Restrict,
+    Cascade,
+    SetNull,
+}
+
+#[derive(Clone)]
+pub struct Column {
+    pub name: &'static str,
+    pub ty: ColumnType,
+    pub primary_key: bool,
+    pub unique: bool,
+    pub not_null: bool,
+    pub default: Option<&'static str>,
+    pub check: Option<&'static str>,
+    pub comment: Option<&'static str>,
+}
+
+#[derive(Clone)]
+pub struct Table {
+    pub name: String,
+    pub columns: Vec<Column>,
+    pub primary_key: Vec<&'static str>,
+    pub unique: Vec<Vec<&'static str>>,
+    // Only created by the strict schema profile, never part of `create_sql`.
+    pub foreign_keys: Vec<ForeignKey>,
+    // Managed outside the migrations, see `index.rs`.
+    pub indexes: Vec<Index>,
+}
+
+// Single-column reference to the `id` of another table.
+#[derive(Clone)]
+pub struct ForeignKey {
+    pub column: &'static str,
+    pub references: String,
+    pub on_delete: OnDelete,
+}
+
+impl ColumnType {
+    pub fn sql(&self) -> String {
+        match self {
+            ColumnType::Uuid => "UUID".to_string(),
+            ColumnType::Text => "TEXT".to_string(),
+            ColumnType::Integer => "INTEGER".to_string(),
+            ColumnType::Boolean => "BOOLEAN".to_string(),
+            ColumnType::Numeric(precision, scale) => format!("NUMERIC({}, {})", precision, scale),
+            ColumnType::Timestamptz(precision) => format!("TIMESTAMPTZ({})", precision),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Index {
+    pub columns: Vec<&'static str>,
+}
+
+impl OnDelete {
+    pub fn sql(&self) -> &'static str {
+        match self {
+            OnDelete::Restrict => "RESTRICT",
+            OnDelete::Cascade => "CASCADE",
+            OnDelete::SetNull => "SET NULL",
+        }
+    }
+}
+
+impl Column {
+    pub fn new(name: &'static str, ty: ColumnType) -> Self {
+        Self {
+            name,
+            ty,
+            primary_key: false,
+            unique: false,
+            not_null: false,
+            default: None,
+            check: None,
+            comment: None,
+        }
+    }
+
+    pub fn primary_key(mut self) -> Self {
+        self.primary_key = true;
+        self
+    }
+
+    pub fn unique(mut self) -> Self {
+        self.unique = true;
+        self
+    }
+
+    pub fn not_null(mut self) -> Self {
+        self.not_null = true;
+        self
+    }
+
+    pub fn default(mut self, expr: &'static str) -> Self {
+        self.default = Some(expr);
+        self
+    }
+
+    pub fn check(mut self, expr: &'static str) -> Self {
+        self.check = Some(expr);
+        self
+    }
+
+    pub fn comment(mut self, text: &'static str) -> Self {
+        self.comment = Some(text);
+        self
+    }
+
+    pub fn sql(&self) -> String {
+        let mut sql = format!("{:<16} {}", self.name, self.ty.sql());
+
+        if self.primary_key {
+            sql.push_str(" PRIMARY KEY");
+        }
+        if self.unique {
+            sql.push_str(" UNIQUE");
+        }
+        if self.not_null {
+            sql.push_str(" NOT NULL");
+        }
+        if let Some(default) = self.default {
+            sql.push_str(&format!(" DEFAULT {}", default));
+        }
+        if let Some(check) = self.check {
+            sql.push_str(&format!(" CHECK ({})", check));
+        }
+
+        sql
+    }
+}
+
+impl Table {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            columns: Vec::new(),
+            primary_key: Vec::new(),
+            unique: Vec::new(),
+            foreign_keys: Vec::new(),
+            indexes: Vec::new(),
+        }
+    }
+
+    pub fn columns(mut self, columns: impl IntoIterator<Item = Column>) -> Self {
+        self.columns.extend(columns);
+        self
+    }
+
+    // Composite primary key, single-column keys use `Column::primary_key`.
+    pub fn primary_key(mut self, columns: &[&'static str]) -> Self {
+        self.primary_key = columns.to_vec();
+        self
+    }
+
+    pub fn unique(mut self, columns: &[&'static str]) -> Self {
+        self.unique.push(columns.to_vec());
+        self
+    }
+
+    pub fn foreign_key(
+        mut self,
+        column: &'static str,
+        references: impl Into<String>,
+        on_delete: OnDelete,
+    ) -> Self {
+        self.foreign_keys.push(ForeignKey {
+            column,
+            references: references.into(),
+            on_delete,
+        });
+        self
+    }
+
+    pub fn index(mut self, columns: &[&'static str]) -> Self {
+        self.indexes.push(Index {
+            columns: columns.to_vec(),
+        });
+        self
+    }
+
+    pub fn index_name(&self, index: &Index) -> String {
+        format!("{}_{}_idx", self.name, index.columns.join("_"))
+    }
+
+    pub fn index_sql(&self, index: &Index, concurrently: bool) -> String {
+        format!(
+            "CREATE INDEX {}IF NOT EXISTS {} ON {} ({})",
+            if concurrently { "CONCURRENTLY " } else { "" },
+            self.index_name(index),
+            self.name,
+            index.columns.join(", ")
+        )
+    }
+
+    // Same name PostgreSQL would generate for an inline REFERENCES.
+    pub fn foreign_key_name(&self, foreign_key: &ForeignKey) -> String {
+        format!("{}_{}_fkey", self.name, foreign_key.column)
+    }
+
+    // Added NOT VALID so that existing rows are checked in a separate step.
+    pub fn foreign_key_sql(&self, foreign_key: &ForeignKey) -> String {
+        format!(
+            "ALTER TABLE {} ADD CONSTRAINT {} FOREIGN KEY ({}) REFERENCES {} (id) ON DELETE {} NOT VALID",
+            self.name,
+            self.foreign_key_name(foreign_key),
+            foreign_key.column,
+            foreign_key.references,
+            foreign_key.on_delete.sql()
+        )
+    }
+
+    pub fn create_sql(&self) -> String {
+        let mut lines: Vec<String> = self.columns.iter().map(|c| c.sql()).collect();
+
+        if !self.primary_key.is_empty() {
+            lines.push(format!("PRIMARY KEY ({})", self.primary_key.join(", ")));
+        }
+        for columns in &self.unique {
+            lines.push(format!("UNIQUE ({})", columns.join(", ")));
+        }
+
+        // Comments go after the comma so that they never swallow it.
+        let count = lines.len();
+        let body: Vec<String> = lines
+            .into_iter()
+            .enumerate()
+            .map(|(i, line)| {
+                let separator = if i + 1 < count { "," } else { "" };
+                let comment = self
+                    .columns
+                    .get(i)
+                    .and_then(|c| c.comment)
+                    .map(|text| format!(" -- {}", text))
+                    .unwrap_or_default();
+                format!("    {}{}{}", line, separator, comment)
+            })
+            .collect();
+
+        format!(
+            "CREATE TABLE IF NOT EXISTS {} (\n{}\n);",
+            self.name,
+            body.join("\n")
+        )
+    }
+}
@@ -0,0 +1,141 @@
+use anyhow::{Context, Result};
+use log::{debug, warn};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+// Entries of a libpq password file, `hostname:port:database:username:password`.
+// `*` matches any value, a backslash escapes `:` and `\`.
+#[derive(Default)]
+pub struct PgPass {
+    entries: Vec<[String; 5]>,
+}
+
+impl PgPass {
+    // A missing file is empty. Like libpq, a file readable by group or others
+    // is ignored with a warning.
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("Failed to read password file `{}`", path.display()));
+            }
+        };
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            let mode = fs::metadata(path)?.permissions().mode();
+            if mode & 0o077 != 0 {
+                warn!(
+                    "Password file `{}` has group or world access and is ignored, it should be 0600.",
+                    path.display()
+                );
+                return Ok(Self::default());
+            }
+        }
+
+        let entries: Vec<[String; 5]> = content
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .filter_map(split_fields)
+            .collect();
+        debug!(
+            "Read {} entries from password file `{}`.",
+            entries.len(),
+            path.display()
+        );
+
+        Ok(Self { entries })
+    }
+
+    // The first matching entry wins.
+    pub fn password(&self, host: &str, port: u16, database: &str, user: &str) -> Option<&str> {
+        let port = port.to_string();
+        let matches = |pattern: &str, value: &str| pattern == "*" || pattern == value;
+
+        self.entries
+            .iter()
+            .find(|[h, p, d, u, _]| {
+                matches(h, host) && matches(p, &port) && matches(d, database) && matches(u, user)
+            })
+            .map(|entry| entry[4].as_str())
+    }
+}
+
+// Lines with fewer than five fields are skipped, the password is the rest of the line.
+fn split_fields(line: &str) -> Option<[String; 5]> {
+    let mut fields = vec![String::new()];
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => fields.last_mut()?.extend(chars.next()),
+            ':' if fields.len() < 5 => fields.push(String::new()),
+            c => fields.last_mut()?.push(c),
+        }
+    }
+
+    fields.try_into().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pgpass(content: &str) -> PgPass {
+        PgPass {
+            entries: content.lines().filter_map(split_fields).collect(),
+        }
+    }
+
+    #[test]
+    fn split_fields_unescapes_colons_and_backslashes() {
+        let fields = split_fields(r"db\:host:5432:my\\db:alice:secret").unwrap();
+        assert_eq!(fields, ["db:host", "5432", r"my\db", "alice", "secret"]);
+    }
+
+    #[test]
+    fn split_fields_keeps_colons_in_the_password() {
+        let fields = split_fields("localhost:5432:postgres:postgres:pa:ss:word").unwrap();
+        assert_eq!(fields[4], "pa:ss:word");
+    }
+
+    #[test]
+    fn split_fields_skips_short_lines() {
+        assert!(split_fields("localhost:5432:postgres:postgres").is_none());
+        assert!(split_fields("").is_none());
+    }
+
+    #[test]
+    fn password_matches_wildcards() {
+        let pgpass = pgpass("*:*:*:bob:any\nlocalhost:*:ytx_auth:*:auth");
+
+        assert_eq!(pgpass.password("db", 6432, "x", "bob"), Some("any"));
+        assert_eq!(
+            pgpass.password("localhost", 5432, "ytx_auth", "alice"),
+            Some("auth")
+        );
+        assert_eq!(
+            pgpass.password("localhost", 5432, "ytx_main", "alice"),
+            None
+        );
+    }
+
+    #[test]
+    fn password_takes_the_first_match() {
+        let pgpass = pgpass("localhost:5432:*:postgres:first\n*:*:*:postgres:second");
+
+        assert_eq!(
+            pgpass.password("localhost", 5432, "postgres", "postgres"),
+            Some("first")
+        );
+        assert_eq!(
+            pgpass.password("localhost", 5433, "postgres", "postgres"),
+            Some("second")
+        );
+    }
+}
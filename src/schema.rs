@@ -1,59 +1,85 @@
-pub fn ytx_user() -> String {
-    r#"
-    CREATE TABLE IF NOT EXISTS ytx_user (
-        id             UUID PRIMARY KEY,        -- Internal user identity (e.g., for business logic)
-        email          TEXT UNIQUE,             -- User contact
-        password_hash  TEXT NOT NULL,           -- Hashed user login password (bcrypt/argon2)
-        register_time  TIMESTAMPTZ(0),
-        updated_time   TIMESTAMPTZ(0),
-        updated_by     UUID,
-        last_login     TIMESTAMPTZ(0),
-        is_valid       BOOLEAN DEFAULT TRUE
-    );
-    "#
-    .to_string()
+use crate::constant::*;
+use crate::model::ColumnType::*;
+use crate::model::*;
+
+pub fn ytx_user() -> Table {
+    Table::new("ytx_user").columns([
+        Column::new("id", Uuid)
+            .primary_key()
+            .comment("Internal user identity (e.g., for business logic)"),
+        Column::new("email", Text).unique().comment("User contact"),
+        Column::new("password_hash", Text)
+            .not_null()
+            .comment("Hashed user login password (bcrypt/argon2)"),
+        Column::new("register_time", Timestamptz(0)),
+        Column::new("updated_time", Timestamptz(0)),
+        Column::new("updated_by", Uuid),
+        Column::new("last_login", Timestamptz(0)),
+        Column::new("is_valid", Boolean).default("TRUE"),
+    ])
 }
 
-pub fn ytx_role_workspace() -> String {
-    r#"
-    CREATE TABLE IF NOT EXISTS ytx_role_workspace (
-        user_id               UUID NOT NULL,                    -- Matches id in ytx_user (manually managed)
-        role                  TEXT NOT NULL,
-        workspace             TEXT NOT NULL,
-        is_access_enabled     BOOLEAN DEFAULT FALSE,            -- Access to workspace
-        register_time         TIMESTAMPTZ(0),
-        updated_time          TIMESTAMPTZ(0),
-        updated_by            UUID,
-        is_valid              BOOLEAN DEFAULT TRUE,
-        PRIMARY KEY (user_id, workspace)
-    );
-    "#
-    .to_string()
+pub fn ytx_role_workspace() -> Table {
+    Table::new("ytx_role_workspace")
+        .columns([
+            Column::new("user_id", Uuid)
+                .not_null()
+                .comment("Matches id in ytx_user (manually managed)"),
+            Column::new("role", Text).not_null(),
+            Column::new("workspace", Text).not_null(),
+            Column::new("is_access_enabled", Boolean)
+                .default("FALSE")
+                .comment("Access to workspace"),
+            Column::new("register_time", Timestamptz(0)),
+            Column::new("updated_time", Timestamptz(0)),
+            Column::new("updated_by", Uuid),
+            Column::new("is_valid", Boolean).default("TRUE"),
+        ])
+        .primary_key(&["user_id", "workspace"])
 }
 
-pub fn ytx_workspace_database() -> String {
-    r#"
-    CREATE TABLE IF NOT EXISTS ytx_workspace_database (
-        workspace        TEXT PRIMARY KEY,
-        database         TEXT NOT NULL,
-        created_time     TIMESTAMPTZ(0),
-        updated_time     TIMESTAMPTZ(0),
-        updated_by       UUID,
-        is_valid         BOOLEAN DEFAULT TRUE
-    );
-    "#
-    .to_string()
+pub fn ytx_workspace_database() -> Table {
+    Table::new("ytx_workspace_database").columns([
+        Column::new("workspace", Text).primary_key(),
+        Column::new("database", Text).not_null(),
+        Column::new("created_time", Timestamptz(0)),
+        Column::new("updated_time", Timestamptz(0)),
+        Column::new("updated_by", Uuid),
+        Column::new("is_valid", Boolean).default("TRUE"),
+    ])
 }
 
-pub fn ytx_meta() -> String {
-    r#"
-        CREATE TABLE IF NOT EXISTS ytx_meta (
-            key TEXT PRIMARY KEY,
-            value BOOLEAN,
-            created_time TIMESTAMPTZ(0) DEFAULT now()
-        );
-        "#
-    .to_string()
+pub fn schema_migrations() -> Table {
+    Table::new("schema_migrations").columns([
+        Column::new("version", Integer).primary_key(),
+        Column::new("name", Text).not_null(),
+        Column::new("checksum", Text).not_null(),
+        Column::new("applied_time", Timestamptz(0)).default("now()"),
+    ])
+}
+
+// Steps of the last `init` run, kept in LOCK_DATABASE because the first steps
+// run before the auth database exists and every run must find it, whatever
+// its maintenance database.
+pub fn ytx_init_journal() -> Table {
+    Table::new("ytx_init_journal").columns([
+        Column::new("step", Text).primary_key(),
+        Column::new("position", Integer).not_null(),
+        Column::new("status", Text)
+            .not_null()
+            .check("status IN ('running', 'done', 'failed')"),
+        Column::new("started_time", Timestamptz(0)).default("now()"),
+        Column::new("finished_time", Timestamptz(0)),
+        Column::new("error", Text),
+    ])
+}
+
+pub fn ytx_meta() -> Table {
+    Table::new("ytx_meta").columns([
+        Column::new("key", Text).primary_key(),
+        Column::new("value", Boolean),
+        Column::new("created_time", Timestamptz(0)).default("now()"),
+    ])
 }
 
 pub fn insert_meta() -> String {
@@ -65,17 +91,14 @@ pub fn insert_meta() -> String {
     .to_string()
 }
 
-pub fn global_config() -> String {
-    r#"
-    CREATE TABLE IF NOT EXISTS global_config (
-        section TEXT PRIMARY KEY,
-        default_unit     INTEGER        DEFAULT 0,
-        document_dir     TEXT           DEFAULT '',
-        updated_time     TIMESTAMPTZ(0) DEFAULT now(),
-        updated_by       UUID
-    );
-    "#
-    .to_string()
+pub fn global_config() -> Table {
+    Table::new("global_config").columns([
+        Column::new("section", Text).primary_key(),
+        Column::new("default_unit", Integer).default("0"),
+        Column::new("document_dir", Text).default("''"),
+        Column::new("updated_time", Timestamptz(0)).default("now()"),
+        Column::new("updated_by", Uuid),
+    ])
 }
 
 pub fn insert_global_config(section: &str) -> String {
@@ -89,318 +112,302 @@ pub fn insert_global_config(section: &str) -> String {
     )
 }
 
-pub fn f_node_table() -> String {
-    r#"
-        CREATE TABLE IF NOT EXISTS finance_node (
-            id               UUID PRIMARY KEY,
-            name             TEXT,
-            code             TEXT,
-            description      TEXT,
-            note             TEXT,
-            kind             INTEGER,
-            direction_rule   BOOLEAN DEFAULT FALSE,
-            unit             INTEGER,
-            initial_total    NUMERIC(16, 4),
-            final_total      NUMERIC(16, 4),
-            user_id          UUID,
-            created_time     TIMESTAMPTZ(0),
-            created_by       UUID,
-            updated_time     TIMESTAMPTZ(0),
-            updated_by       UUID,
-            is_valid         BOOLEAN DEFAULT TRUE
-        );
-        "#
-    .to_string()
+pub fn f_node_table() -> Table {
+    Table::new("finance_node")
+        .columns(node_columns())
+        .columns([
+            Column::new("initial_total", Numeric(16, 4)),
+            Column::new("final_total", Numeric(16, 4)),
+        ])
+        .columns(audit_columns())
+        .index(&["user_id"])
+        .index(&["is_valid"])
 }
 
-pub fn f_entry_table() -> String {
-    r#"
-        CREATE TABLE IF NOT EXISTS finance_entry (
-            id             UUID PRIMARY KEY,
-            issued_time    TIMESTAMPTZ(0),
-            code           TEXT,
-            lhs_node       UUID,
-            lhs_rate       NUMERIC(16, 8) CHECK (lhs_rate   >  0),
-            lhs_debit      NUMERIC(12, 4) CHECK (lhs_debit  >= 0),
-            lhs_credit     NUMERIC(12, 4) CHECK (lhs_credit >= 0),
-            description    TEXT,
-            support_node   UUID,
-            document       TEXT,
-            is_checked     BOOLEAN DEFAULT FALSE,
-            rhs_credit     NUMERIC(16, 8) CHECK (rhs_credit >= 0),
-            rhs_debit      NUMERIC(12, 4) CHECK (rhs_debit  >= 0),
-            rhs_rate       NUMERIC(12, 4) CHECK (rhs_rate   >  0),
-            rhs_node       UUID,
-            user_id        UUID,
-            created_time   TIMESTAMPTZ(0),
-            created_by     UUID,
-            updated_time   TIMESTAMPTZ(0),
-            updated_by     UUID,
-            is_valid       BOOLEAN DEFAULT TRUE
-        );
-        "#
-    .to_string()
+pub fn f_entry_table() -> Table {
+    Table::new("finance_entry")
+        .columns(entry_columns())
+        .columns([
+            Column::new("lhs_rate", Numeric(16, 8)).check("lhs_rate > 0"),
+            Column::new("lhs_debit", Numeric(12, 4)).check("lhs_debit >= 0"),
+            Column::new("lhs_credit", Numeric(12, 4)).check("lhs_credit >= 0"),
+            Column::new("description", Text),
+            Column::new("support_node", Uuid),
+            Column::new("document", Text),
+            Column::new("is_checked", Boolean).default("FALSE"),
+            Column::new("rhs_credit", Numeric(16, 8)).check("rhs_credit >= 0"),
+            Column::new("rhs_debit", Numeric(12, 4)).check("rhs_debit >= 0"),
+            Column::new("rhs_rate", Numeric(12, 4)).check("rhs_rate > 0"),
+            Column::new("rhs_node", Uuid),
+        ])
+        .columns(audit_columns())
+        .foreign_key("lhs_node", "finance_node", OnDelete::Restrict)
+        .foreign_key("rhs_node", "finance_node", OnDelete::Restrict)
+        .index(&["lhs_node"])
+        .index(&["rhs_node"])
+        .index(&["issued_time"])
+        .index(&["user_id"])
+        .index(&["is_valid"])
 }
 
-pub fn i_node_table() -> String {
-    r#"
-        CREATE TABLE IF NOT EXISTS item_node (
-            id               UUID PRIMARY KEY,
-            name             TEXT,
-            code             TEXT,
-            description      TEXT,
-            note             TEXT,
-            kind             INTEGER,
-            direction_rule   BOOLEAN DEFAULT FALSE,
-            unit             INTEGER,
-            color            TEXT,
-            unit_price       NUMERIC(16, 4),
-            commission       NUMERIC(16, 4),
-            initial_total    NUMERIC(16, 4),
-            final_total      NUMERIC(16, 4),
-            user_id          UUID,
-            created_time     TIMESTAMPTZ(0),
-            created_by       UUID,
-            updated_time     TIMESTAMPTZ(0),
-            updated_by       UUID,
-            is_valid         BOOLEAN DEFAULT TRUE
-        );
-        "#
-    .to_string()
+pub fn i_node_table() -> Table {
+    Table::new("item_node")
+        .columns(node_columns())
+        .columns([
+            Column::new("color", Text),
+            Column::new("unit_price", Numeric(16, 4)),
+            Column::new("commission", Numeric(16, 4)),
+            Column::new("initial_total", Numeric(16, 4)),
+            Column::new("final_total", Numeric(16, 4)),
+        ])
+        .columns(audit_columns())
+        .index(&["user_id"])
+        .index(&["is_valid"])
 }
 
-pub fn i_entry_table() -> String {
-    r#"
-        CREATE TABLE IF NOT EXISTS item_entry (
-            id             UUID PRIMARY KEY,
-            issued_time    TIMESTAMPTZ(0),
-            code           TEXT,
-            lhs_node       UUID,
-            unit_cost      NUMERIC(12, 4),
-            lhs_debit      NUMERIC(12, 4) CHECK (lhs_debit >= 0),
-            lhs_credit     NUMERIC(12, 4) CHECK (lhs_credit >= 0),
-            description    TEXT,
-            support_node   UUID,
-            document       TEXT,
-            is_checked     BOOLEAN DEFAULT FALSE,
-            rhs_credit     NUMERIC(12, 4) CHECK (rhs_credit >= 0),
-            rhs_debit      NUMERIC(12, 4) CHECK (rhs_debit >= 0),
-            rhs_node       UUID,
-            user_id        UUID,
-            created_time   TIMESTAMPTZ(0),
-            created_by     UUID,
-            updated_time   TIMESTAMPTZ(0),
-            updated_by     UUID,
-            is_valid       BOOLEAN DEFAULT TRUE
-        );
-        "#
-    .to_string()
+pub fn i_entry_table() -> Table {
+    costed_entry_table(ITEM)
 }
 
-pub fn t_node_table() -> String {
-    r#"
-        CREATE TABLE IF NOT EXISTS task_node (
-            id               UUID PRIMARY KEY,
-            name             TEXT,
-            code             TEXT,
-            description      TEXT,
-            note             TEXT,
-            kind             INTEGER,
-            direction_rule   BOOLEAN DEFAULT FALSE,
-            unit             INTEGER,
-            issued_time      TIMESTAMPTZ(0),
-            color            TEXT,
-            document         TEXT,
-            is_finished      BOOLEAN DEFAULT FALSE,
-            initial_total    NUMERIC(16, 4),
-            final_total      NUMERIC(16, 4),
-            user_id          UUID,
-            created_time     TIMESTAMPTZ(0),
-            created_by       UUID,
-            updated_time     TIMESTAMPTZ(0),
-            updated_by       UUID,
-            is_valid         BOOLEAN DEFAULT TRUE
-        );
-        "#
-    .to_string()
+pub fn t_node_table() -> Table {
+    Table::new("task_node")
+        .columns(node_columns())
+        .columns([
+            Column::new("issued_time", Timestamptz(0)),
+            Column::new("color", Text),
+            Column::new("document", Text),
+            Column::new("is_finished", Boolean).default("FALSE"),
+            Column::new("initial_total", Numeric(16, 4)),
+            Column::new("final_total", Numeric(16, 4)),
+        ])
+        .columns(audit_columns())
+        .index(&["user_id"])
+        .index(&["is_valid"])
 }
 
-pub fn t_entry_table() -> String {
-    r#"
-        CREATE TABLE IF NOT EXISTS task_entry (
-            id             UUID PRIMARY KEY,
-            issued_time    TIMESTAMPTZ(0),
-            code           TEXT,
-            lhs_node       UUID,
-            unit_cost      NUMERIC(12, 4),
-            lhs_debit      NUMERIC(12, 4) CHECK (lhs_debit >= 0),
-            lhs_credit     NUMERIC(12, 4) CHECK (lhs_credit >= 0),
-            description    TEXT,
-            support_node   UUID,
-            document       TEXT,
-            is_checked     BOOLEAN DEFAULT FALSE,
-            rhs_credit     NUMERIC(12, 4) CHECK (rhs_credit >= 0),
-            rhs_debit      NUMERIC(12, 4) CHECK (rhs_debit >= 0),
-            rhs_node       UUID,
-            user_id        UUID,
-            created_time   TIMESTAMPTZ(0),
-            created_by     UUID,
-            updated_time   TIMESTAMPTZ(0),
-            updated_by     UUID,
-            is_valid       BOOLEAN DEFAULT TRUE
-        );
-        "#
-    .to_string()
+pub fn t_entry_table() -> Table {
+    costed_entry_table(TASK)
 }
 
-pub fn s_node_table() -> String {
-    r#"
-        CREATE TABLE IF NOT EXISTS stakeholder_node (
-            id               UUID PRIMARY KEY,
-            name             TEXT,
-            code             TEXT,
-            description      TEXT,
-            note             TEXT,
-            kind             INTEGER,
-            direction_rule   BOOLEAN DEFAULT FALSE,
-            unit             INTEGER,
-            payment_term     INTEGER,
-            initial_total    NUMERIC(16, 4),
-            final_total      NUMERIC(16, 4),
-            user_id          UUID,
-            created_time     TIMESTAMPTZ(0),
-            created_by       UUID,
-            updated_time     TIMESTAMPTZ(0),
-            updated_by       UUID,
-            is_valid         BOOLEAN DEFAULT TRUE
-        );
-        "#
-    .to_string()
+pub fn s_node_table() -> Table {
+    Table::new("stakeholder_node")
+        .columns(node_columns())
+        .columns([
+            Column::new("payment_term", Integer),
+            Column::new("initial_total", Numeric(16, 4)),
+            Column::new("final_total", Numeric(16, 4)),
+        ])
+        .columns(audit_columns())
+        .index(&["user_id"])
+        .index(&["is_valid"])
 }
 
-pub fn s_entry_table() -> String {
-    r#"
-        CREATE TABLE IF NOT EXISTS stakeholder_entry (
-            id                 UUID PRIMARY KEY,
-            issued_time        TIMESTAMPTZ(0),
-            code               TEXT,
-            lhs_node           UUID,
-            unit_price         NUMERIC(12, 4),
-            description        TEXT,
-            external_item      UUID,
-            document           TEXT,
-            is_checked         BOOLEAN DEFAULT FALSE,
-            rhs_node           UUID,
-            user_id            UUID,
-            created_time       TIMESTAMPTZ(0),
-            created_by         UUID,
-            updated_time       TIMESTAMPTZ(0),
-            updated_by         UUID,
-            is_valid           BOOLEAN DEFAULT TRUE,
-            UNIQUE(lhs_node, rhs_node)
-        );
-        "#
-    .to_string()
+pub fn s_entry_table() -> Table {
+    Table::new("stakeholder_entry")
+        .columns(entry_columns())
+        .columns([
+            Column::new("unit_price", Numeric(12, 4)),
+            Column::new("description", Text),
+            Column::new("external_item", Uuid),
+            Column::new("document", Text),
+            Column::new("is_checked", Boolean).default("FALSE"),
+            Column::new("rhs_node", Uuid),
+        ])
+        .columns(audit_columns())
+        // lhs_node lookups use the unique constraint's index.
+        .unique(&["lhs_node", "rhs_node"])
+        .foreign_key("lhs_node", "stakeholder_node", OnDelete::Cascade)
+        .foreign_key("rhs_node", "item_node", OnDelete::Restrict)
+        .foreign_key("external_item", "item_node", OnDelete::SetNull)
+        .index(&["rhs_node"])
+        .index(&["issued_time"])
+        .index(&["user_id"])
+        .index(&["is_valid"])
 }
 
-pub fn o_node_table(order: &str) -> String {
-    format!(
-        r#"
-        CREATE TABLE IF NOT EXISTS {}_node (
-            id                UUID PRIMARY KEY,
-            name              TEXT,
-            code              TEXT,
-            description       TEXT,
-            note              TEXT,
-            kind              INTEGER,
-            direction_rule    BOOLEAN DEFAULT FALSE,
-            unit              INTEGER,
-            party             UUID,
-            employee          UUID,
-            issued_time       TIMESTAMPTZ(0),
-            first_total       NUMERIC(16, 4),
-            second_total      NUMERIC(16, 4),
-            is_finished       BOOLEAN DEFAULT FALSE,
-            initial_total     NUMERIC(16, 4),
-            discount_total    NUMERIC(16, 4),
-            final_total       NUMERIC(16, 4),
-            settlement_node   UUID,
-            user_id           UUID,
-            created_time      TIMESTAMPTZ(0),
-            created_by        UUID,
-            updated_time      TIMESTAMPTZ(0),
-            updated_by        UUID,
-            is_valid          BOOLEAN DEFAULT TRUE
-        );
-        "#,
-        order
-    )
+pub fn o_node_table(order: &str) -> Table {
+    Table::new(format!("{}_node", order))
+        .columns(node_columns())
+        .columns([
+            Column::new("party", Uuid),
+            Column::new("employee", Uuid),
+            Column::new("issued_time", Timestamptz(0)),
+            Column::new("first_total", Numeric(16, 4)),
+            Column::new("second_total", Numeric(16, 4)),
+            Column::new("is_finished", Boolean).default("FALSE"),
+            Column::new("initial_total", Numeric(16, 4)),
+            Column::new("discount_total", Numeric(16, 4)),
+            Column::new("final_total", Numeric(16, 4)),
+            Column::new("settlement_node", Uuid),
+        ])
+        .columns(audit_columns())
+        .foreign_key("party", "stakeholder_node", OnDelete::Restrict)
+        .foreign_key("employee", "stakeholder_node", OnDelete::SetNull)
+        .foreign_key(
+            "settlement_node",
+            format!("{}_settlement", order),
+            OnDelete::SetNull,
+        )
+        .index(&["party"])
+        .index(&["issued_time"])
+        .index(&["settlement_node"])
+        .index(&["user_id"])
+        .index(&["is_valid"])
 }
 
-pub fn o_entry_table(order: &str) -> String {
-    format!(
-        r#"
-        CREATE TABLE IF NOT EXISTS {}_entry (
-            id                  UUID PRIMARY KEY,
-            issued_time         TIMESTAMPTZ(0),
-            code                TEXT,
-            lhs_node            UUID,
-            unit_price          NUMERIC(12, 4),
-            first               NUMERIC(12, 4),
-            second              NUMERIC(12, 4),
-            description         TEXT,
-            external_item       UUID,
-            document            TEXT,
-            is_checked          BOOLEAN DEFAULT FALSE,
-            discount            NUMERIC(12, 4),
-            final               NUMERIC(12, 4),
-            initial             NUMERIC(12, 4),
-            discount_price      NUMERIC(12, 4),
-            rhs_node            UUID,
-            user_id             UUID,
-            created_time        TIMESTAMPTZ(0),
-            created_by          UUID,
-            updated_time        TIMESTAMPTZ(0),
-            updated_by          UUID,
-            is_valid            BOOLEAN DEFAULT TRUE
-        );
-        "#,
-        order
-    )
+pub fn o_entry_table(order: &str) -> Table {
+    Table::new(format!("{}_entry", order))
+        .columns(entry_columns())
+        .columns([
+            Column::new("unit_price", Numeric(12, 4)),
+            Column::new("first", Numeric(12, 4)),
+            Column::new("second", Numeric(12, 4)),
+            Column::new("description", Text),
+            Column::new("external_item", Uuid),
+            Column::new("document", Text),
+            Column::new("is_checked", Boolean).default("FALSE"),
+            Column::new("discount", Numeric(12, 4)),
+            Column::new("final", Numeric(12, 4)),
+            Column::new("initial", Numeric(12, 4)),
+            Column::new("discount_price", Numeric(12, 4)),
+            Column::new("rhs_node", Uuid),
+        ])
+        .columns(audit_columns())
+        .foreign_key("lhs_node", format!("{}_node", order), OnDelete::Cascade)
+        .foreign_key("rhs_node", "item_node", OnDelete::Restrict)
+        .foreign_key("external_item", "item_node", OnDelete::SetNull)
+        .index(&["lhs_node"])
+        .index(&["rhs_node"])
+        .index(&["issued_time"])
+        .index(&["user_id"])
+        .index(&["is_valid"])
 }
 
-pub fn o_settlement_table(order: &str) -> String {
-    format!(
-        r#"
-            CREATE TABLE IF NOT EXISTS {}_settlement (
-                id               UUID PRIMARY KEY,
-                party            UUID,
-                issued_time      TIMESTAMPTZ(0),
-                description      TEXT,
-                is_finished      BOOLEAN DEFAULT FALSE,
-                initial_total    NUMERIC(16, 4),
-                user_id          UUID,
-                created_time     TIMESTAMPTZ(0),
-                created_by       UUID,
-                updated_time     TIMESTAMPTZ(0),
-                updated_by       UUID,
-                is_valid         BOOLEAN DEFAULT TRUE
-            );
-            "#,
-        order
-    )
+pub fn o_settlement_table(order: &str) -> Table {
+    Table::new(format!("{}_settlement", order))
+        .columns([
+            Column::new("id", Uuid).primary_key(),
+            Column::new("party", Uuid),
+            Column::new("issued_time", Timestamptz(0)),
+            Column::new("description", Text),
+            Column::new("is_finished", Boolean).default("FALSE"),
+            Column::new("initial_total", Numeric(16, 4)),
+        ])
+        .columns(audit_columns())
+        .foreign_key("party", "stakeholder_node", OnDelete::Restrict)
+        .index(&["party"])
+        .index(&["issued_time"])
+        .index(&["user_id"])
+        .index(&["is_valid"])
 }
 
-pub fn path_table(table_name: &str) -> String {
-    format!(
-        r#"
-        CREATE TABLE IF NOT EXISTS {}_path (
-            ancestor      UUID,
-            descendant    UUID,
-            distance      INTEGER DEFAULT 1 CHECK (distance = 1),
-            PRIMARY KEY (ancestor, descendant)
-        );
-        "#,
-        table_name
-    )
+pub fn path_table(table_name: &str) -> Table {
+    Table::new(format!("{}_path", table_name))
+        .columns([
+            Column::new("ancestor", Uuid),
+            Column::new("descendant", Uuid),
+            Column::new("distance", Integer)
+                .default("1")
+                .check("distance = 1"),
+        ])
+        .primary_key(&["ancestor", "descendant"])
+        .foreign_key(
+            "ancestor",
+            format!("{}_node", table_name),
+            OnDelete::Cascade,
+        )
+        .foreign_key(
+            "descendant",
+            format!("{}_node", table_name),
+            OnDelete::Cascade,
+        )
+        .index(&["descendant"])
+}
+
+// Every table of a main database, referenced tables first.
+pub fn main_tables() -> Vec<Table> {
+    let mut tables = vec![
+        ytx_meta(),
+        global_config(),
+        f_node_table(),
+        s_node_table(),
+        i_node_table(),
+        t_node_table(),
+        f_entry_table(),
+        s_entry_table(),
+        t_entry_table(),
+        i_entry_table(),
+    ];
+
+    tables.extend(SECTIONS.iter().map(|section| path_table(section)));
+
+    for order in [SALE, PURCHASE] {
+        tables.push(o_settlement_table(order));
+        tables.push(o_node_table(order));
+        tables.push(o_entry_table(order));
+    }
+
+    tables
+}
+
+// Item and task entries share the same layout.
+fn costed_entry_table(section: &str) -> Table {
+    Table::new(format!("{}_entry", section))
+        .columns(entry_columns())
+        .columns([
+            Column::new("unit_cost", Numeric(12, 4)),
+            Column::new("lhs_debit", Numeric(12, 4)).check("lhs_debit >= 0"),
+            Column::new("lhs_credit", Numeric(12, 4)).check("lhs_credit >= 0"),
+            Column::new("description", Text),
+            Column::new("support_node", Uuid),
+            Column::new("document", Text),
+            Column::new("is_checked", Boolean).default("FALSE"),
+            Column::new("rhs_credit", Numeric(12, 4)).check("rhs_credit >= 0"),
+            Column::new("rhs_debit", Numeric(12, 4)).check("rhs_debit >= 0"),
+            Column::new("rhs_node", Uuid),
+        ])
+        .columns(audit_columns())
+        .foreign_key("lhs_node", format!("{}_node", section), OnDelete::Restrict)
+        .foreign_key("rhs_node", format!("{}_node", section), OnDelete::Restrict)
+        .index(&["lhs_node"])
+        .index(&["rhs_node"])
+        .index(&["issued_time"])
+        .index(&["user_id"])
+        .index(&["is_valid"])
+}
+
+// Leading columns of every *_node table.
+fn node_columns() -> [Column; 8] {
+    [
+        Column::new("id", Uuid).primary_key(),
+        Column::new("name", Text),
+        Column::new("code", Text),
+        Column::new("description", Text),
+        Column::new("note", Text),
+        Column::new("kind", Integer),
+        Column::new("direction_rule", Boolean).default("FALSE"),
+        Column::new("unit", Integer),
+    ]
+}
+
+// Leading columns of every *_entry table.
+fn entry_columns() -> [Column; 4] {
+    [
+        Column::new("id", Uuid).primary_key(),
+        Column::new("issued_time", Timestamptz(0)),
+        Column::new("code", Text),
+        Column::new("lhs_node", Uuid),
+    ]
+}
+
+// Ownership and audit columns closing node, entry and settlement tables.
+fn audit_columns() -> [Column; 6] {
+    [
+        Column::new("user_id", Uuid),
+        Column::new("created_time", Timestamptz(0)),
+        Column::new("created_by", Uuid),
+        Column::new("updated_time", Timestamptz(0)),
+        Column::new("updated_by", Uuid),
+        Column::new("is_valid", Boolean).default("TRUE"),
+    ]
 }
@@ -0,0 +1,101 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use hmac::{Hmac, Mac};
+use postgres_protocol::password::scram_sha_256;
+use sha2::{Digest, Sha256};
+
+type HmacSha256 = Hmac<Sha256>;
+
+// SCRAM-SHA-256 verifier with a random salt, in the format PostgreSQL stores.
+// Sent instead of the plaintext password so that the password itself never
+// reaches server logs or pg_stat_statements.
+pub fn scram_verifier(password: &str) -> String {
+    scram_sha_256(password.as_bytes())
+}
+
+// Checks a password against a stored `SCRAM-SHA-256$<iterations>:<salt>$<StoredKey>:<ServerKey>`
+// verifier. None when the verifier is not SCRAM-SHA-256 (e.g. md5) and cannot be checked.
+// Passwords are not SASLprep-normalized, which only matters for non-ASCII passwords:
+// they are reported as changed and set again.
+pub fn verify_scram(password: &str, verifier: &str) -> Option<bool> {
+    let rest = verifier.strip_prefix("SCRAM-SHA-256$")?;
+    let (params, keys) = rest.split_once('$')?;
+    let (iterations, salt) = params.split_once(':')?;
+    let (stored_key, server_key) = keys.split_once(':')?;
+
+    let iterations: u32 = iterations.parse().ok()?;
+    let salt = STANDARD.decode(salt).ok()?;
+
+    let (expected_stored, expected_server) = scram_keys(password.as_bytes(), &salt, iterations);
+
+    Some(
+        STANDARD.encode(expected_stored) == stored_key
+            && STANDARD.encode(expected_server) == server_key,
+    )
+}
+
+// StoredKey and ServerKey of RFC 5802.
+fn scram_keys(password: &[u8], salt: &[u8], iterations: u32) -> (Vec<u8>, Vec<u8>) {
+    let salted = hi(password, salt, iterations);
+
+    let client_key = hmac(&salted, b"Client Key");
+    let stored_key = Sha256::digest(&client_key).to_vec();
+    let server_key = hmac(&salted, b"Server Key");
+
+    (stored_key, server_key)
+}
+
+// PBKDF2 with HMAC-SHA-256 and a single output block.
+fn hi(password: &[u8], salt: &[u8], iterations: u32) -> Vec<u8> {
+    let mut block = salt.to_vec();
+    block.extend_from_slice(&1u32.to_be_bytes());
+
+    let mut u = hmac(password, &block);
+    let mut result = u.clone();
+    for _ in 1..iterations {
+        u = hmac(password, &u);
+        for (r, x) in result.iter_mut().zip(&u) {
+            *r ^= x;
+        }
+    }
+
+    result
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verifies_the_password_of_a_generated_verifier() {
+        let verifier = scram_verifier("s3cret'pa$$");
+
+        assert_eq!(verify_scram("s3cret'pa$$", &verifier), Some(true));
+        assert_eq!(verify_scram("s3cret'pa$", &verifier), Some(false));
+        assert_eq!(verify_scram("", &verifier), Some(false));
+    }
+
+    #[test]
+    fn salts_differ_between_verifiers() {
+        assert_ne!(scram_verifier("secret"), scram_verifier("secret"));
+    }
+
+    #[test]
+    fn other_formats_cannot_be_checked() {
+        assert_eq!(
+            verify_scram("secret", "md5a3556571e93b0d20722ba62be61e8c2d"),
+            None
+        );
+        assert_eq!(verify_scram("secret", "SCRAM-SHA-256$4096"), None);
+        assert_eq!(
+            verify_scram("secret", "SCRAM-SHA-256$many:c2FsdA==$a:b"),
+            None
+        );
+    }
+}
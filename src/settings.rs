@@ -0,0 +1,472 @@
+use crate::connection::*;
+use crate::constant::*;
+
+use anyhow::{Context, Result, bail};
+use std::collections::HashMap;
+use std::env::{self, VarError};
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+use toml::{Table, Value};
+
+// A configuration value: its environment variable, its key in the config
+// file and its default. This table is the only place defaults are written down.
+pub struct Setting {
+    pub env: &'static str,
+    pub file: &'static str,
+    pub default: Option<&'static str>,
+    // Redacted when the configuration is printed.
+    pub secret: bool,
+}
+
+const fn setting(env: &'static str, file: &'static str, default: Option<&'static str>) -> Setting {
+    Setting {
+        env,
+        file,
+        default,
+        secret: false,
+    }
+}
+
+const fn secret(env: &'static str, file: &'static str) -> Setting {
+    Setting {
+        env,
+        file,
+        default: None,
+        secret: true,
+    }
+}
+
+pub const SETTINGS: &[Setting] = &[
+    setting("WAIT_TIMEOUT", "wait_timeout", Some("30")),
+    setting("LOCK_TIMEOUT", "lock_timeout", Some("60")),
+    setting("LOCK_DATABASE", "lock_database", Some("postgres")),
+    setting("SCHEMA_PROFILE", "schema_profile", Some("standard")),
+    setting("POSTGRES_URL", "postgres.url", None),
+    setting("POSTGRES_ROLE", "postgres.role", None),
+    secret("POSTGRES_PASSWORD", "postgres.password"),
+    setting("POSTGRES_SSLROOTCERT", "postgres.sslrootcert", None),
+    setting("POSTGRES_SSLCERT", "postgres.sslcert", None),
+    setting("POSTGRES_SSLKEY", "postgres.sslkey", None),
+    setting("VAULT_ADDR", "vault.addr", Some("http://127.0.0.1:8200")),
+    secret("POSTGRES_TOKEN", "vault.token"),
+    setting("VAULT_TOKEN_FILE", "vault.token_file", None),
+    setting("VAULT_ROLE_ID", "vault.role_id", None),
+    secret("VAULT_SECRET_ID", "vault.secret_id"),
+    setting(
+        "VAULT_APPROLE_MOUNT",
+        "vault.approle_mount",
+        Some("approle"),
+    ),
+    setting("VAULT_NAMESPACE", "vault.namespace", None),
+    setting("VAULT_CACERT", "vault.cacert", None),
+    setting("VAULT_CAPATH", "vault.capath", None),
+    setting("VAULT_CLIENT_CERT", "vault.client_cert", None),
+    setting("VAULT_CLIENT_KEY", "vault.client_key", None),
+    setting("VAULT_CLIENT_TIMEOUT", "vault.timeout", Some("60")),
+    setting("VAULT_KV_MOUNT", "vault.kv_mount", Some(VAULT_KV_MOUNT)),
+    setting("VAULT_KV_VERSION", "vault.kv_version", Some("2")),
+    setting(
+        "POSTGRES_SECRET_PATH",
+        "vault.postgres_secret_path",
+        Some(POSTGRES_SECRET_PATH),
+    ),
+    setting(
+        "POSTGRES_SECRET_VERSION",
+        "vault.postgres_secret_version",
+        None,
+    ),
+    setting(
+        "YTX_SECRET_PATH",
+        "vault.ytx_secret_path",
+        Some(YTX_SECRET_PATH),
+    ),
+    setting("YTX_SECRET_VERSION", "vault.ytx_secret_version", None),
+    setting(
+        "VAULT_DATABASE_MOUNT",
+        "vault.database.mount",
+        Some("database"),
+    ),
+    setting(
+        "VAULT_DATABASE_CONNECTION",
+        "vault.database.connection",
+        Some("ytx"),
+    ),
+    setting("VAULT_DATABASE_URL", "vault.database.url", None),
+    setting(
+        "VAULT_DATABASE_ROTATION_PERIOD",
+        "vault.database.rotation_period",
+        Some("24h"),
+    ),
+    setting("AUTH_DB", "auth.database", Some("ytx_auth")),
+    setting(
+        "AUTH_READWRITE_ROLE",
+        "auth.readwrite_role",
+        Some("ytx_auth_readwrite"),
+    ),
+    secret("AUTH_READWRITE_PASSWORD", "auth.readwrite_password"),
+    setting("MAIN_DB", "main.database", Some("ytx_main")),
+    setting("MAIN_WORKSPACE", "main.workspace", Some("ytx_workspace")),
+    setting(
+        "MAIN_READWRITE_ROLE",
+        "main.readwrite_role",
+        Some("ytx_main_readwrite"),
+    ),
+    secret("MAIN_READWRITE_PASSWORD", "main.readwrite_password"),
+    setting(
+        "MAIN_READONLY_ROLE",
+        "main.readonly_role",
+        Some("ytx_main_readonly"),
+    ),
+    secret("MAIN_READONLY_PASSWORD", "main.readonly_password"),
+];
+
+// Values of the config file by environment variable name, set once at startup.
+type FileValues = HashMap<&'static str, String>;
+static FILE_VALUES: OnceLock<FileValues> = OnceLock::new();
+
+// Workspaces and their main databases listed in the config file.
+pub struct FileWorkspace {
+    pub name: String,
+    pub database: String,
+}
+
+// Reads the TOML config file. Unknown keys are rejected so that a typo does
+// not silently fall back to a default.
+pub fn load_file(path: &Path) -> Result<Vec<FileWorkspace>> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config file `{}`", path.display()))?;
+    let (values, workspaces) = parse_config(&content)
+        .with_context(|| format!("Invalid config file `{}`", path.display()))?;
+
+    FILE_VALUES
+        .set(values)
+        .map_err(|_| anyhow::anyhow!("Config file loaded twice"))?;
+    Ok(workspaces)
+}
+
+// Reads a manifest of workspaces, a TOML file with only [[workspaces]] entries
+// in the format of the config file.
+pub fn load_manifest(path: &Path) -> Result<Vec<FileWorkspace>> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read manifest `{}`", path.display()))?;
+    parse_manifest(&content).with_context(|| format!("Invalid manifest `{}`", path.display()))
+}
+
+// The environment wins over the config file, which wins over the default.
+// Empty environment variables count as unset, so that an `.env` copied from
+// the template does not hide the file.
+pub fn var(key: impl AsRef<str>) -> Result<String, VarError> {
+    let key = key.as_ref();
+    resolve(key, env::var(key), FILE_VALUES.get())
+}
+
+// Prints every setting with the place its value comes from, secrets redacted.
+pub fn print_settings(workspaces: &[FileWorkspace]) {
+    let mut section = "";
+
+    for setting in SETTINGS {
+        let (table, name) = setting.file.rsplit_once('.').unwrap_or(("", setting.file));
+        if table != section {
+            println!("\n[{}]", table);
+            section = table;
+        }
+
+        let value = match env::var(setting.env) {
+            Ok(value) if !value.is_empty() => Some((value, "environment")),
+            _ => source_value(setting.env, FILE_VALUES.get()),
+        };
+
+        match value {
+            Some((value, source)) => {
+                let shown = if setting.secret {
+                    "********".to_string()
+                } else if setting.env == "POSTGRES_URL" {
+                    redact_connection_string(&value)
+                } else {
+                    value
+                };
+                println!(
+                    "{} = {}  # {}, {}",
+                    name,
+                    toml_string(&shown),
+                    setting.env,
+                    source
+                );
+            }
+            None => println!("# {} =  # {}, not set", name, setting.env),
+        }
+    }
+
+    for workspace in workspaces {
+        println!("\n[[workspaces]]");
+        println!("name = {}", toml_string(&workspace.name));
+        println!("database = {}", toml_string(&workspace.database));
+    }
+}
+
+fn parse_config(content: &str) -> Result<(FileValues, Vec<FileWorkspace>)> {
+    let mut table: Table = content.parse()?;
+
+    let workspaces = match table.remove("workspaces") {
+        Some(value) => read_workspaces(value)?,
+        None => Vec::new(),
+    };
+
+    let mut leaves = Vec::new();
+    flatten("", &table, &mut leaves)?;
+
+    let mut values = HashMap::new();
+    for (key, value) in leaves {
+        let Some(setting) = SETTINGS.iter().find(|s| s.file == key) else {
+            bail!("Unknown setting `{}`", key);
+        };
+        values.insert(setting.env, value);
+    }
+
+    Ok((values, workspaces))
+}
+
+fn parse_manifest(content: &str) -> Result<Vec<FileWorkspace>> {
+    let mut table: Table = content.parse()?;
+
+    let workspaces = match table.remove("workspaces") {
+        Some(value) => read_workspaces(value)?,
+        None => Vec::new(),
+    };
+    if let Some(key) = table.keys().next() {
+        bail!(
+            "Unknown key `{}`, a manifest only lists [[workspaces]]",
+            key
+        );
+    }
+
+    Ok(workspaces)
+}
+
+fn resolve(
+    key: &str,
+    from_env: Result<String, VarError>,
+    file_values: Option<&FileValues>,
+) -> Result<String, VarError> {
+    match from_env {
+        Ok(value) if !value.is_empty() => Ok(value),
+        from_env => match source_value(key, file_values) {
+            Some((value, _)) => Ok(value),
+            None => from_env,
+        },
+    }
+}
+
+fn source_value(key: &str, file_values: Option<&FileValues>) -> Option<(String, &'static str)> {
+    if let Some(value) = file_values.and_then(|values| values.get(key)) {
+        return Some((value.clone(), "config file"));
+    }
+
+    SETTINGS
+        .iter()
+        .find(|s| s.env == key)
+        .and_then(|s| s.default)
+        .map(|default| (default.to_string(), "default"))
+}
+
+fn flatten(prefix: &str, table: &Table, leaves: &mut Vec<(String, String)>) -> Result<()> {
+    for (key, value) in table {
+        let path = if prefix.is_empty() {
+            key.clone()
+        } else {
+            format!("{}.{}", prefix, key)
+        };
+
+        match value {
+            Value::Table(table) => flatten(&path, table, leaves)?,
+            Value::String(s) => leaves.push((path, s.clone())),
+            Value::Integer(i) => leaves.push((path, i.to_string())),
+            Value::Boolean(b) => leaves.push((path, b.to_string())),
+            _ => bail!("Setting `{}` must be a string, integer or boolean", path),
+        }
+    }
+
+    Ok(())
+}
+
+fn read_workspaces(value: Value) -> Result<Vec<FileWorkspace>> {
+    let Value::Array(entries) = value else {
+        bail!("`workspaces` must be an array of tables, written as [[workspaces]]");
+    };
+
+    entries.into_iter().map(read_workspace).collect()
+}
+
+// Like the settings, unknown keys and values of the wrong type are rejected
+// instead of falling back to the default database.
+fn read_workspace(entry: Value) -> Result<FileWorkspace> {
+    let Value::Table(entry) = entry else {
+        bail!("`workspaces` must be an array of tables, written as [[workspaces]]");
+    };
+
+    if let Some(key) = entry
+        .keys()
+        .find(|key| !["name", "database"].contains(&key.as_str()))
+    {
+        bail!(
+            "Unknown key `{}` in [[workspaces]] entry, expected `name` and `database`",
+            key
+        );
+    }
+
+    let name = match entry.get("name") {
+        Some(Value::String(name)) => name.clone(),
+        Some(_) => bail!("`name` of a [[workspaces]] entry must be a string"),
+        None => bail!("Every [[workspaces]] entry needs a name"),
+    };
+
+    // Same default as `workspace add`.
+    let database = match entry.get("database") {
+        Some(Value::String(database)) => database.clone(),
+        Some(_) => bail!("`database` of workspace '{}' must be a string", name),
+        None => name.clone(),
+    };
+
+    Ok(FileWorkspace { name, database })
+}
+
+fn toml_string(value: &str) -> String {
+    Value::String(value.to_string()).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(result: Result<impl Sized>) -> String {
+        match result {
+            Ok(_) => panic!("expected an error"),
+            Err(e) => format!("{:#}", e),
+        }
+    }
+
+    #[test]
+    fn parse_config_maps_keys_to_variables() {
+        let (values, workspaces) = parse_config(
+            r#"
+            lock_timeout = 5
+
+            [auth]
+            database = "other_auth"
+
+            [vault.database]
+            mount = "db"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(values["LOCK_TIMEOUT"], "5");
+        assert_eq!(values["AUTH_DB"], "other_auth");
+        assert_eq!(values["VAULT_DATABASE_MOUNT"], "db");
+        assert!(workspaces.is_empty());
+    }
+
+    #[test]
+    fn parse_config_rejects_unknown_keys() {
+        assert!(error(parse_config("lock_timout = 5")).contains("`lock_timout`"));
+        assert!(error(parse_config("[auth]\ndatbase = \"x\"")).contains("`auth.datbase`"));
+    }
+
+    #[test]
+    fn parse_config_rejects_arrays_as_settings() {
+        assert!(error(parse_config("lock_timeout = [5]")).contains("must be a string"));
+    }
+
+    #[test]
+    fn workspace_database_defaults_to_the_name() {
+        let (_, workspaces) = parse_config(
+            r#"
+            [[workspaces]]
+            name = "acme"
+            database = "ytx_acme"
+
+            [[workspaces]]
+            name = "globex"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(workspaces.len(), 2);
+        assert_eq!(
+            (workspaces[0].name.as_str(), workspaces[0].database.as_str()),
+            ("acme", "ytx_acme")
+        );
+        assert_eq!(
+            (workspaces[1].name.as_str(), workspaces[1].database.as_str()),
+            ("globex", "globex")
+        );
+    }
+
+    #[test]
+    fn workspace_entries_reject_unknown_keys_and_wrong_types() {
+        let typo = "[[workspaces]]\nname = \"globex\"\ndatabse = \"ytx_globex\"";
+        assert!(error(parse_manifest(typo)).contains("`databse`"));
+
+        assert!(error(parse_manifest("[[workspaces]]\nname = 5")).contains("must be a string"));
+        assert!(
+            error(parse_manifest("[[workspaces]]\nname = \"a\"\ndatabase = 5"))
+                .contains("must be a string")
+        );
+        assert!(error(parse_manifest("[[workspaces]]\ndatabase = \"a\"")).contains("needs a name"));
+    }
+
+    #[test]
+    fn workspaces_must_be_an_array_of_tables() {
+        assert!(error(parse_manifest("workspaces = \"acme\"")).contains("array of tables"));
+        assert!(error(parse_manifest("workspaces = [\"acme\"]")).contains("array of tables"));
+        assert!(error(parse_config("[workspaces]\nname = \"acme\"")).contains("array of tables"));
+    }
+
+    #[test]
+    fn parse_manifest_only_accepts_workspaces() {
+        assert!(error(parse_manifest("lock_timeout = 5")).contains("`lock_timeout`"));
+        assert!(parse_manifest("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn environment_wins_over_file_over_default() {
+        let file = HashMap::from([("LOCK_TIMEOUT", "5".to_string())]);
+
+        assert_eq!(
+            resolve("LOCK_TIMEOUT", Ok("7".to_string()), Some(&file)),
+            Ok("7".to_string())
+        );
+        assert_eq!(
+            resolve("LOCK_TIMEOUT", Err(VarError::NotPresent), Some(&file)),
+            Ok("5".to_string())
+        );
+        assert_eq!(
+            resolve("LOCK_TIMEOUT", Err(VarError::NotPresent), None),
+            Ok("60".to_string())
+        );
+    }
+
+    #[test]
+    fn empty_environment_variables_count_as_unset() {
+        let file = HashMap::from([("AUTH_DB", "other_auth".to_string())]);
+
+        assert_eq!(
+            resolve("AUTH_DB", Ok(String::new()), Some(&file)),
+            Ok("other_auth".to_string())
+        );
+        assert_eq!(
+            resolve("MAIN_DB", Ok(String::new()), Some(&file)),
+            Ok("ytx_main".to_string())
+        );
+        // Without file value or default the empty value is kept.
+        assert_eq!(
+            resolve("VAULT_NAMESPACE", Ok(String::new()), None),
+            Ok(String::new())
+        );
+        assert_eq!(
+            resolve("VAULT_NAMESPACE", Err(VarError::NotPresent), None),
+            Err(VarError::NotPresent)
+        );
+    }
+}
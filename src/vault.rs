@@ -0,0 +1,397 @@
+use crate::wait::*;
+
+use anyhow::{Context, Result};
+use log::{debug, warn};
+use reqwest::StatusCode;
+use reqwest::blocking::{Client, RequestBuilder, Response};
+use reqwest::header::{HeaderMap, HeaderValue};
+use reqwest::{Certificate, Identity};
+use serde_json::{Map, Value, json};
+use std::cell::RefCell;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+// Alphabet of generated passwords, safe in connection strings and shells.
+const PASSWORD_CHARS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+const PASSWORD_LENGTH: usize = 32;
+
+pub enum VaultAuth {
+    Token(String),
+    // Sink file of a Vault Agent, read again for every request so that
+    // renewed tokens are picked up.
+    TokenFile(PathBuf),
+    // Exchanged for a client token on first use, the token is revoked on drop.
+    AppRole {
+        mount: String,
+        role_id: String,
+        secret_id: String,
+    },
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum KvVersion {
+    V1,
+    V2,
+}
+
+// Mount of the KV secrets engine holding the passwords.
+pub struct KvMount {
+    pub path: String,
+    pub version: KvVersion,
+}
+
+// HTTP settings, named after the environment variables of the Vault CLI.
+pub struct VaultOptions {
+    // Enterprise namespace, sent as X-Vault-Namespace with every request.
+    pub namespace: Option<String>,
+    pub ca_cert: Option<PathBuf>,
+    // Directory of PEM files, all of them are trusted.
+    pub ca_path: Option<PathBuf>,
+    pub client_cert: Option<PathBuf>,
+    pub client_key: Option<PathBuf>,
+    pub timeout: Duration,
+    // Connection errors and 503 responses are retried for this long.
+    pub wait_timeout: Duration,
+}
+
+pub struct Vault {
+    addr: String,
+    client: Client,
+    auth: VaultAuth,
+    kv: KvMount,
+    wait_timeout: Duration,
+    // Client token obtained by logging in, None for a static token.
+    login_token: RefCell<Option<String>>,
+}
+
+// A secret together with the version it was read at, for check-and-set writes.
+// KV v1 has no versions, the version is always 0 there.
+pub struct VaultSecret {
+    pub data: Map<String, Value>,
+    pub version: u64,
+}
+
+impl Vault {
+    pub fn new(addr: &str, auth: VaultAuth, kv: KvMount, options: &VaultOptions) -> Result<Self> {
+        Ok(Self {
+            addr: addr.trim_end_matches('/').to_string(),
+            client: build_client(options).context("Failed to build Vault HTTP client")?,
+            auth,
+            kv,
+            wait_timeout: options.wait_timeout,
+            login_token: RefCell::new(None),
+        })
+    }
+
+    pub fn kv_version(&self) -> KvVersion {
+        self.kv.version
+    }
+
+    // Reads the latest version of a secret, or `version` if it is pinned (KV v2 only).
+    pub fn read_data(&self, secret_path: &str, version: Option<u64>) -> Result<Value> {
+        let mut url = self.kv_url(secret_path);
+        if let Some(version) = version {
+            url.push_str(&format!("?version={}", version));
+        }
+
+        let resp = self.send(self.authorized(self.client.get(url))?)?;
+        if !resp.status().is_success() {
+            anyhow::bail!("HTTP error {}", resp.status());
+        }
+
+        let json: Value = resp.json()?;
+        Ok(self.kv_data(&json).clone())
+    }
+
+    // None when nothing has been written to the path yet.
+    pub fn read_secret(&self, secret_path: &str) -> Result<Option<VaultSecret>> {
+        let resp = self.send(self.authorized(self.client.get(self.kv_url(secret_path)))?)?;
+        if resp.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !resp.status().is_success() {
+            anyhow::bail!("HTTP error {}", resp.status());
+        }
+
+        let json: Value = resp.json()?;
+        Ok(Some(VaultSecret {
+            data: self.kv_data(&json).as_object().cloned().unwrap_or_default(),
+            version: json["data"]["metadata"]["version"].as_u64().unwrap_or(0),
+        }))
+    }
+
+    // Writes a new version only if the current one is still `cas` (0 for a new secret).
+    pub fn write_secret(
+        &self,
+        secret_path: &str,
+        data: &Map<String, Value>,
+        cas: u64,
+    ) -> Result<()> {
+        if self.kv.version == KvVersion::V1 {
+            anyhow::bail!("Check-and-set writes require the KV v2 secrets engine");
+        }
+
+        let body = json!({ "options": { "cas": cas }, "data": data });
+        let resp = self.send(
+            self.authorized(self.client.post(self.kv_url(secret_path)))?
+                .json(&body),
+        )?;
+
+        check_status(resp)
+    }
+
+    // Plain write to any Vault API path, e.g. to configure a secrets engine.
+    pub fn write(&self, path: &str, body: &Value) -> Result<()> {
+        let resp = self.send(
+            self.authorized(self.client.post(self.url(path)))?
+                .json(body),
+        )?;
+
+        check_status(resp)
+    }
+
+    fn authorized(&self, request: RequestBuilder) -> Result<RequestBuilder> {
+        Ok(request.header("X-Vault-Token", self.token()?))
+    }
+
+    fn token(&self) -> Result<String> {
+        match &self.auth {
+            VaultAuth::Token(token) => Ok(token.clone()),
+            VaultAuth::TokenFile(path) => {
+                let token = fs::read_to_string(path).with_context(|| {
+                    format!("Failed to read Vault token file `{}`", path.display())
+                })?;
+                let token = token.trim();
+                if token.is_empty() {
+                    anyhow::bail!("Vault token file `{}` is empty", path.display());
+                }
+                Ok(token.to_string())
+            }
+            VaultAuth::AppRole {
+                mount,
+                role_id,
+                secret_id,
+            } => {
+                if let Some(token) = self.login_token.borrow().as_ref() {
+                    return Ok(token.clone());
+                }
+
+                let token = self
+                    .login(mount, role_id, secret_id)
+                    .with_context(|| format!("Vault AppRole login at auth/{} failed", mount))?;
+                *self.login_token.borrow_mut() = Some(token.clone());
+                Ok(token)
+            }
+        }
+    }
+
+    fn login(&self, mount: &str, role_id: &str, secret_id: &str) -> Result<String> {
+        let resp = self.send(
+            self.client
+                .post(self.url(&format!("auth/{}/login", mount)))
+                .json(&json!({ "role_id": role_id, "secret_id": secret_id })),
+        )?;
+
+        let status = resp.status();
+        let json: Value = resp.json().unwrap_or_default();
+        if !status.is_success() {
+            anyhow::bail!("HTTP error {}: {}", status, json["errors"]);
+        }
+
+        debug!("Logged in to Vault with AppRole.");
+        json["auth"]["client_token"]
+            .as_str()
+            .map(|t| t.to_string())
+            .context("Vault login response has no client token")
+    }
+
+    // Revokes the token obtained by logging in, a static token is left alone.
+    pub fn revoke(&self) -> Result<()> {
+        let Some(token) = self.login_token.borrow_mut().take() else {
+            return Ok(());
+        };
+
+        let resp = self
+            .client
+            .post(self.url("auth/token/revoke-self"))
+            .header("X-Vault-Token", token)
+            .send()?;
+
+        check_status(resp)?;
+        debug!("Vault token revoked.");
+        Ok(())
+    }
+
+    // Waits for Vault to come up: connection errors and 503 (sealed, or no
+    // active node yet) are retried until WAIT_TIMEOUT has passed.
+    fn send(&self, request: RequestBuilder) -> Result<Response> {
+        let result = retry(
+            "Vault",
+            self.wait_timeout,
+            |e| {
+                e.is::<Unavailable>()
+                    || e.downcast_ref::<reqwest::Error>()
+                        .is_some_and(|e| e.is_connect())
+            },
+            || {
+                let request = request
+                    .try_clone()
+                    .context("Vault request cannot be retried")?;
+                let resp = request.send()?;
+                if resp.status() == StatusCode::SERVICE_UNAVAILABLE {
+                    let json: Value = resp.json().unwrap_or_default();
+                    return Err(Unavailable(json["errors"].to_string()).into());
+                }
+                Ok(resp)
+            },
+        );
+
+        result.with_context(|| format!("Vault at {} is not available", self.addr))
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}/v1/{}", self.addr, path)
+    }
+
+    // KV v2 serves secrets below `<mount>/data/`, KV v1 directly below the mount.
+    fn kv_url(&self, secret_path: &str) -> String {
+        match self.kv.version {
+            KvVersion::V1 => self.url(&format!("{}/{}", self.kv.path, secret_path)),
+            KvVersion::V2 => self.url(&format!("{}/data/{}", self.kv.path, secret_path)),
+        }
+    }
+
+    fn kv_data<'a>(&self, json: &'a Value) -> &'a Value {
+        match self.kv.version {
+            KvVersion::V1 => &json["data"],
+            KvVersion::V2 => &json["data"]["data"],
+        }
+    }
+}
+
+impl Drop for Vault {
+    fn drop(&mut self) {
+        if let Err(e) = self.revoke() {
+            warn!("Failed to revoke Vault token: {:#}", e);
+        }
+    }
+}
+
+// HTTP 503 from Vault.
+#[derive(Debug)]
+struct Unavailable(String);
+
+impl fmt::Display for Unavailable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0.contains("sealed") {
+            write!(f, "Vault is sealed, unseal it first")
+        } else {
+            write!(f, "HTTP error 503 Service Unavailable: {}", self.0)
+        }
+    }
+}
+
+impl std::error::Error for Unavailable {}
+
+pub fn get_vault_password(data: &serde_json::Value, key: &str) -> Result<String> {
+    data.get(key)
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_string())
+        .ok_or_else(|| anyhow::anyhow!("Vault key '{}' not found or not a string", key))
+}
+
+pub fn generate_password() -> Result<String> {
+    let mut password = String::with_capacity(PASSWORD_LENGTH);
+    let mut buf = [0u8; 64];
+
+    // Rejection sampling keeps every character equally likely.
+    let limit = 256 - 256 % PASSWORD_CHARS.len();
+    while password.len() < PASSWORD_LENGTH {
+        getrandom::fill(&mut buf)
+            .map_err(|e| anyhow::anyhow!("Failed to read system randomness: {}", e))?;
+        for &byte in buf.iter().filter(|&&b| (b as usize) < limit) {
+            if password.len() == PASSWORD_LENGTH {
+                break;
+            }
+            password.push(PASSWORD_CHARS[byte as usize % PASSWORD_CHARS.len()] as char);
+        }
+    }
+
+    Ok(password)
+}
+
+fn build_client(options: &VaultOptions) -> Result<Client> {
+    let mut builder = Client::builder()
+        .timeout(options.timeout)
+        .connect_timeout(options.timeout);
+
+    if let Some(namespace) = &options.namespace {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "X-Vault-Namespace",
+            HeaderValue::from_str(namespace).context("Invalid VAULT_NAMESPACE")?,
+        );
+        builder = builder.default_headers(headers);
+    }
+
+    let mut ca_files = Vec::new();
+    if let Some(path) = &options.ca_cert {
+        ca_files.push(path.clone());
+    }
+    if let Some(dir) = &options.ca_path {
+        let entries = fs::read_dir(dir)
+            .with_context(|| format!("Failed to read VAULT_CAPATH `{}`", dir.display()))?;
+        for entry in entries {
+            let path = entry?.path();
+            if path.is_file() {
+                ca_files.push(path);
+            }
+        }
+        ca_files.sort();
+    }
+
+    for path in &ca_files {
+        let pem = fs::read(path)
+            .with_context(|| format!("Failed to read CA certificate `{}`", path.display()))?;
+        let certs = Certificate::from_pem_bundle(&pem)
+            .with_context(|| format!("Invalid CA certificate `{}`", path.display()))?;
+        if certs.is_empty() && options.ca_cert.as_ref() == Some(path) {
+            anyhow::bail!("No PEM certificate found in `{}`", path.display());
+        }
+        for cert in certs {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+
+    match (&options.client_cert, &options.client_key) {
+        (Some(cert), Some(key)) => {
+            let cert = fs::read(cert).with_context(|| {
+                format!(
+                    "Failed to read Vault client certificate `{}`",
+                    cert.display()
+                )
+            })?;
+            let key = fs::read(key)
+                .with_context(|| format!("Failed to read Vault client key `{}`", key.display()))?;
+            let identity = Identity::from_pkcs8_pem(&cert, &key)
+                .context("Invalid Vault client certificate or key, the key must be PKCS#8 PEM")?;
+            builder = builder.identity(identity);
+        }
+        (None, None) => {}
+        _ => anyhow::bail!("VAULT_CLIENT_CERT and VAULT_CLIENT_KEY must be set together"),
+    }
+
+    Ok(builder.build()?)
+}
+
+fn check_status(resp: reqwest::blocking::Response) -> Result<()> {
+    let status = resp.status();
+    if !status.is_success() {
+        let json: Value = resp.json().unwrap_or_default();
+        anyhow::bail!("HTTP error {}: {}", status, json["errors"]);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,42 @@
+use anyhow::Result;
+use log::info;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+const FIRST_DELAY: Duration = Duration::from_millis(250);
+const MAX_DELAY: Duration = Duration::from_secs(5);
+
+// Runs `attempt` until it succeeds, fails with an error `transient` does not
+// accept, or `timeout` has passed. The delay between attempts doubles from
+// 250ms up to 5s. A zero timeout makes a single attempt.
+pub fn retry<T>(
+    service: &str,
+    timeout: Duration,
+    transient: impl Fn(&anyhow::Error) -> bool,
+    mut attempt: impl FnMut() -> Result<T>,
+) -> Result<T> {
+    let deadline = Instant::now() + timeout;
+    let mut delay = FIRST_DELAY;
+
+    loop {
+        match attempt() {
+            Err(e) if transient(&e) => {
+                let left = deadline.saturating_duration_since(Instant::now());
+                if left.is_zero() {
+                    return Err(e);
+                }
+
+                let wait = delay.min(left);
+                info!(
+                    "{} is not ready ({:#}), retrying in {:.1}s.",
+                    service,
+                    e,
+                    wait.as_secs_f64()
+                );
+                sleep(wait);
+                delay = (delay * 2).min(MAX_DELAY);
+            }
+            result => return result,
+        }
+    }
+}